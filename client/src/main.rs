@@ -2,27 +2,291 @@
 extern crate log;
 extern crate env_logger;
 
-use network;
+use network::alias::Aliases;
+use network::error::Error;
+use network::server_message::{Hand, LegalAction};
+use network::handshake::{Capability, Handshake};
+use network::envelope::Kind;
+use network::tls::{self, Fingerprint, TlsStream};
+use network::{Message, Protocol, Request, Response, ServerMessage};
+use std::env;
+use std::fs;
 use std::io::prelude::*;
 use std::io::{stdin, BufReader};
 use std::net::TcpStream;
+#[cfg(unix)]
+use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+#[cfg(unix)]
+use std::os::unix::net::UnixStream;
 use std::thread;
 
+/// What the client knows about its session from the messages the server has sent
+enum State {
+    LoggedOut,
+    LoggedIn,
+    Playing,
+}
+
+/// Time between pings, well below the server's idle timeout
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(20);
+
+fn format_hand(hand: &Hand) -> String {
+    format!("{} ({})", hand.cards.join(" "), hand.total)
+}
+
+/// Actions the way they are typed, with the cost of the ones that take from the balance
+fn format_actions(actions: &[LegalAction]) -> String {
+    let actions: Vec<String> = actions
+        .iter()
+        .map(|legal| match legal.cost {
+            0 => format!("\"{}\"", legal.action),
+            cost => format!("\"{}\" ({})", legal.action, cost),
+        })
+        .collect();
+
+    actions.join(", ")
+}
+
+/// Prints the message and returns the state the session is in after it
+fn render(message: ServerMessage, actions: &[LegalAction], state: State) -> State {
+    let state = match message {
+        ServerMessage::Welcome => {
+            println!("Connected to the server");
+            State::LoggedOut
+        }
+        ServerMessage::LoggedIn { balance } => {
+            println!("Logged in, balance: {}", balance);
+            State::LoggedIn
+        }
+        ServerMessage::Balance { balance } => {
+            println!("Balance: {}", balance);
+            return state;
+        }
+        ServerMessage::Deposited { amount, balance } => {
+            println!("Deposited {}, balance: {}", amount, balance);
+            state
+        }
+        ServerMessage::HandState { dealer, player } => {
+            println!("Dealer: {}\nPlayer: {}", format_hand(&dealer), format_hand(&player));
+            State::Playing
+        }
+        ServerMessage::RoundResult { outcome, dealer, player, balance } => {
+            println!("Dealer: {}\nPlayer: {}", format_hand(&dealer), format_hand(&player));
+            println!("{}! Balance: {}", outcome, balance);
+            println!("Press Enter to play again with the same bet");
+            State::LoggedIn
+        }
+        ServerMessage::ProtocolChanged { protocol } => {
+            debug!("Server is using {} protocol", protocol);
+            return state;
+        }
+        ServerMessage::LanguageChanged { language } => {
+            println!("{}", message.text(language));
+            return state;
+        }
+        ServerMessage::Session { .. } => {
+            debug!("Server started a session");
+            return state;
+        }
+        ServerMessage::Pong => {
+            debug!("Server answered ping");
+            return state;
+        }
+        ServerMessage::Error { code, text } => {
+            println!("Error: {}", text);
+            if code == Error::NotLoggedIn.code() {
+                State::LoggedOut
+            } else {
+                state
+            }
+        }
+        message => {
+            println!("{}", message);
+            return state;
+        }
+    };
+
+    let hint = match state {
+        State::LoggedOut => String::from("Type \"register <name> <password>\" to start a new game or \"login <name> <password>\" to resume one"),
+        State::LoggedIn => String::from("Type \"deposit <int>\" to add to your balance or \"start <int>\" to start a new game"),
+        State::Playing => format!("Type {}", format_actions(actions)),
+    };
+    println!("{}", hint);
+
+    state
+}
+
+/// Only the user can read the session file, anyone with the token can continue the game
+fn save_session(path: &str, token: &str) -> std::io::Result<()> {
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    options.mode(0o600);
+
+    let mut file = options.open(path)?;
+    // Mode is only used for new files, an existing one may have been readable by others
+    #[cfg(unix)]
+    file.set_permissions(fs::Permissions::from_mode(0o600))?;
+    file.write_all(token.as_bytes())
+}
+
+/// Value following the flag, e.g. the path in "--unix /tmp/blackjack.sock"
+fn argument<'a>(args: &'a [String], flag: &str) -> Option<&'a String> {
+    args.iter().position(|arg| arg == flag).and_then(|index| args.get(index + 1))
+}
+
 fn main() {
     env_logger::init();
 
-    let stream = TcpStream::connect("localhost:2024").unwrap();
+    let args: Vec<String> = env::args().collect();
+    let unix_socket = argument(&args, "--unix");
 
-    let read_stream = stream
-        .try_clone()
-        .expect("Failed to clone the stream for reading");
+    // Shorthands on top of the default ones with --alias <alias>=<command>, can be given many times
+    let mut aliases = Aliases::default();
+    for (flag, definition) in args.iter().zip(args.iter().skip(1)) {
+        if flag == "--alias" {
+            if let Err(e) = aliases.define(definition) {
+                println!("{}: {}", e, e.error());
+                return;
+            }
+        }
+    }
+
+    // Server is checked against --ca <path>, --pin <sha256 fingerprint> or both, either one turns TLS on
+    let ca = argument(&args, "--ca").map(Path::new);
+    let pin = match argument(&args, "--pin").map(|pin| pin.parse::<Fingerprint>()) {
+        Some(Ok(pin)) => Some(pin),
+        Some(Err(e)) => {
+            println!("{}", e);
+            return;
+        },
+        None => None,
+    };
+    let tls = if ca.is_some() || pin.is_some() {
+        match tls::client_config(ca, pin) {
+            Ok(config) => Some(config),
+            Err(e) => {
+                println!("{}", e);
+                return;
+            },
+        }
+    } else {
+        None
+    };
 
+    let (mut stream, read_stream): (Box<dyn Write + Send>, Box<dyn Read + Send>) = match unix_socket {
+        #[cfg(unix)]
+        Some(path) => {
+            let stream = UnixStream::connect(path).unwrap();
+            let read_stream = stream.try_clone().expect("Failed to clone the stream for reading");
+            (Box::new(stream), Box::new(read_stream))
+        },
+        _ => {
+            let stream = TcpStream::connect("localhost:2024").unwrap();
+            match tls {
+                Some(config) => match TlsStream::connect(stream, config, "localhost") {
+                    Ok(stream) => (Box::new(stream.clone()), Box::new(stream)),
+                    Err(e) => {
+                        println!("Secure connection to the server failed: {}", e);
+                        return;
+                    },
+                },
+                None => {
+                    let read_stream = stream.try_clone().expect("Failed to clone the stream for reading");
+                    (Box::new(stream), Box::new(read_stream))
+                },
+            }
+        },
+    };
+
+    let mut reader = BufReader::new(read_stream);
+
+    let mut capabilities = vec![Capability::Json];
+    if args.iter().any(|arg| arg == "--binary") {
+        capabilities.push(Capability::Binary);
+    }
+
+    // Server switches to the most compact format both support, without any the messages are printed as they are
+    network::send(&mut stream, Handshake::hello(capabilities.clone())).expect("Failed to send hello to the server");
+    let protocol = match network::read::read(&mut reader).map(|reply| reply.parse::<Handshake>()) {
+        Ok(Ok(hello @ Handshake::Hello { .. })) => {
+            let supported = |capability| capabilities.contains(&capability) && hello.supports(&capability);
+            if supported(Capability::Binary) {
+                Protocol::Binary
+            } else if supported(Capability::Json) {
+                Protocol::Json
+            } else {
+                warn!("Server doesn't support JSON messages");
+                Protocol::Text
+            }
+        },
+        Ok(Ok(Handshake::Reject(reason))) => {
+            println!("Server refused the connection: {}", reason);
+            return;
+        },
+        Ok(Err(e)) => {
+            println!("Server sent an invalid handshake: {}", e);
+            return;
+        },
+        Err(e) => {
+            println!("Handshake with the server failed: {}", e);
+            return;
+        },
+    };
+    debug!("Using {} protocol", protocol);
+
+    // Token of the last session is kept in --session <path>, a dropped connection continues where it was left
+    let session = argument(&args, "--session").cloned();
+    if let Some(token) = session.as_ref().and_then(|path| fs::read_to_string(path).ok()) {
+        let frame = Request::new(None, Message::Resume(token.trim().to_string())).encode(protocol);
+        if let Err(e) = network::send_frame(&mut stream, protocol.codec(), &frame) {
+            warn!("Resuming the session failed: {}", e);
+        }
+    }
+
+    let stream = Arc::new(Mutex::new(stream));
+
+    // Server closes connections that stay quiet for too long, pings keep it open while the player is thinking
+    let heartbeat_stream = stream.clone();
+    thread::spawn(move || loop {
+        thread::sleep(HEARTBEAT_INTERVAL);
+
+        let frame = Request::new(None, Message::Ping).encode(protocol);
+        let mut stream = heartbeat_stream.lock().unwrap();
+        if let Err(e) = network::send_frame(&mut *stream, protocol.codec(), &frame) {
+            debug!("Heartbeat stopped: {}", e);
+            break;
+        }
+    });
+
+    // Empty line right after a round repeats its bet
+    let round_over = Arc::new(AtomicBool::new(false));
+    let input_round_over = round_over.clone();
     thread::spawn(move || {
+        let mut id = 0;
         for line in stdin().lock().lines() {
+            let line = match line {
+                Ok(line) if line.trim().is_empty() => {
+                    if !input_round_over.load(Ordering::SeqCst) {
+                        continue;
+                    }
+                    Ok(Message::Rebet.to_string())
+                },
+                line => line,
+            };
+
             match line {
-                Ok(line) => match line.parse::<network::Message>() {
+                Ok(line) => match aliases.parse(&line) {
+                    Ok(Message::Protocol(_)) => println!("Protocol is chosen by the client"),
                     Ok(message) => {
-                        if let Err(e) = network::send(&stream, message) {
+                        id += 1;
+                        let frame = Request::new(Some(id), message).encode(protocol);
+                        let mut stream = stream.lock().unwrap();
+                        if let Err(e) = network::send_frame(&mut *stream, protocol.codec(), &frame) {
                             error!("{}", e);
                             println!("Failed to send the command to the server");
                         }
@@ -40,19 +304,44 @@ fn main() {
         }
     });
 
-    let mut reader = BufReader::new(read_stream);
+    let mut state = State::LoggedOut;
 
     loop {
-        match network::read::read(&mut reader) {
-            Ok(message) => println!("{}", message),
-            Err(e) => {
-                if e.kind() == network::read::ErrorKind::ConnectionLost {
+        match network::read::read_frame(&mut reader, protocol.codec(), network::read::DEFAULT_MAX_FRAME_SIZE) {
+            Ok(frame) => match Response::decode(&frame, protocol) {
+                Ok(response) => {
+                    match (response.kind, response.id) {
+                        (Kind::Reply, Some(id)) => debug!("Reply to request {}", id),
+                        (Kind::Reply, None) => debug!("Reply"),
+                        (Kind::Event, _) => debug!("Event from the server"),
+                    }
+                    if let (ServerMessage::Session { token, .. }, Some(path)) = (&response.message, &session) {
+                        if let Err(e) = save_session(path, token) {
+                            warn!("Unable to save the session to {}: {}", path, e);
+                        }
+                    }
+
+                    let finished = matches!(response.message, ServerMessage::RoundResult { .. });
+                    state = render(response.message, &response.actions, state);
+                    let rebet = finished || (round_over.load(Ordering::SeqCst) && matches!(state, State::LoggedIn));
+                    round_over.store(rebet, Ordering::SeqCst);
+                },
+                // Server without JSON support sends plain text
+                Err(_) => println!("{}", String::from_utf8_lossy(&frame)),
+            },
+            Err(e) => match e.kind() {
+                network::read::ErrorKind::TimedOut => warn!("{}", e),
+                network::read::ErrorKind::ConnectionLost => {
                     println!("Connection to the server lost");
                     break;
-                } else {
-                    warn!("{}", e);
-                }
-            }
+                },
+                // Rest of the stream can't be split into messages anymore
+                _ => {
+                    error!("{}", e);
+                    println!("Connection to the server is broken");
+                    break;
+                },
+            },
         }
     }
 }
@@ -0,0 +1,108 @@
+use crate::command::Command;
+use crate::error::Error;
+use crate::{InvalidMessageError, MessageResult};
+use std::collections::HashMap;
+
+/// Shorthand keywords for commands, e.g. "h" for "hit". Only the keyword is replaced, so "bet 10" is "start 10"
+#[derive(PartialEq, Debug, Clone)]
+pub struct Aliases {
+    aliases: HashMap<String, Command>,
+}
+
+impl Aliases {
+    /// No aliases at all, only the commands themselves
+    pub fn new() -> Self {
+        Self { aliases: HashMap::new() }
+    }
+
+    /// Commands can't be aliases themselves, "hit" always means hit
+    pub fn insert(&mut self, alias: &str, command: Command) -> Result<(), InvalidMessageError> {
+        let alias = alias.trim().to_lowercase();
+        if alias.is_empty() || alias.contains(char::is_whitespace) || alias.parse::<Command>().is_ok() {
+            return Err(InvalidMessageError { error: Error::InvalidAlias, message: alias });
+        }
+
+        self.aliases.insert(alias, command);
+        Ok(())
+    }
+
+    /// Adds an alias written as "<alias>=<command>", e.g. "h=hit"
+    pub fn define(&mut self, definition: &str) -> Result<(), InvalidMessageError> {
+        let mut split = definition.splitn(2, '=');
+        match (split.next(), split.next()) {
+            (Some(alias), Some(command)) => self.insert(alias, command.parse()?),
+            _ => Err(InvalidMessageError { error: Error::InvalidAlias, message: definition.to_string() }),
+        }
+    }
+
+    pub fn command(&self, alias: &str) -> Option<Command> {
+        self.aliases.get(&alias.to_lowercase()).copied()
+    }
+
+    /// Parses the text message with the aliases replaced by the commands they stand for
+    pub fn parse(&self, s: &str) -> MessageResult {
+        let s = s.trim();
+        let mut split = s.splitn(2, char::is_whitespace);
+
+        match split.next().and_then(|keyword| self.command(keyword)) {
+            Some(command) => format!("{} {}", command, split.next().unwrap_or("")).parse(),
+            None => s.parse(),
+        }
+    }
+}
+
+impl Default for Aliases {
+    fn default() -> Self {
+        let mut aliases = Self::new();
+        let defaults = [
+            ("h", Command::Hit),
+            ("s", Command::Stand),
+            ("d", Command::Double),
+            ("bet", Command::Start),
+            ("deal", Command::Start),
+            ("quit", Command::Exit),
+        ];
+
+        for (alias, command) in defaults.iter() {
+            aliases.aliases.insert(alias.to_string(), *command);
+        }
+
+        aliases
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Message;
+
+    #[test]
+    fn defaults() {
+        let aliases = Aliases::default();
+
+        assert_eq!(Message::Hit, aliases.parse("h").unwrap());
+        assert_eq!(Message::Stand, aliases.parse(" S ").unwrap());
+        assert_eq!(Message::Start(10), aliases.parse("bet 10").unwrap());
+        assert_eq!(Message::Start(10), aliases.parse("deal 10").unwrap());
+        assert_eq!(Message::Exit, aliases.parse("quit").unwrap());
+        // Commands still work and the wire format stays the same
+        assert_eq!(Message::Hit, aliases.parse("hit").unwrap());
+        assert_eq!("start 10", aliases.parse("bet 10").unwrap().to_string());
+        assert_eq!(Error::MissingArgument, aliases.parse("bet").unwrap_err().error());
+    }
+
+    #[test]
+    fn configured() {
+        let mut aliases = Aliases::new();
+        assert!(aliases.parse("h").is_err());
+
+        aliases.define("hh=hit").unwrap();
+        aliases.define("wager = start").unwrap();
+        assert_eq!(Message::Hit, aliases.parse("HH").unwrap());
+        assert_eq!(Message::Start(5), aliases.parse("wager 5").unwrap());
+
+        assert_eq!(Error::InvalidAlias, aliases.define("stand=hit").unwrap_err().error());
+        assert_eq!(Error::InvalidAlias, aliases.define("hit").unwrap_err().error());
+        assert_eq!(Error::UnknownCommand, aliases.define("x=fly").unwrap_err().error());
+    }
+}
@@ -0,0 +1,336 @@
+use crate::command::Command;
+use crate::error::Error;
+use crate::server_message::{CommandHelp, LegalAction, Outcome, ServerMessage};
+use crate::InvalidMessageError;
+use serde::{Deserialize, Serialize};
+use std::{fmt, str::FromStr};
+
+/// Language of the player-facing text, chosen separately for every connection. Commands stay in English
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone, Copy, Default)]
+pub enum Language {
+    #[default]
+    #[serde(rename = "en")]
+    English,
+    #[serde(rename = "et")]
+    Estonian,
+}
+
+impl FromStr for Language {
+    type Err = InvalidMessageError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_ref() {
+            "en" => Ok(Self::English),
+            "et" => Ok(Self::Estonian),
+            _ => Err(InvalidMessageError { error: Error::UnknownLanguage, message: format!("Unknown language {}", s) }),
+        }
+    }
+}
+
+impl fmt::Display for Language {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let text = match self {
+            Self::English => "en",
+            Self::Estonian => "et",
+        };
+
+        write!(f, "{}", text)
+    }
+}
+
+pub fn error(error: &Error, language: Language) -> String {
+    let text = match (error, language) {
+        (Error::UnknownCommand, Language::English) => "Server couldn't understand the command",
+        (Error::UnknownCommand, Language::Estonian) => "Server ei saanud käsust aru",
+        (Error::NotLoggedIn, Language::English) => "Not logged in",
+        (Error::NotLoggedIn, Language::Estonian) => "Pole sisse logitud",
+        (Error::ServerError, Language::English) => "Something went wrong on the server",
+        (Error::ServerError, Language::Estonian) => "Serveris läks midagi valesti",
+        (Error::InvalidFrame, Language::English) => "Message framing was broken",
+        (Error::InvalidFrame, Language::Estonian) => "Sõnumi raamistus oli vigane",
+        (Error::IdleTimeout, Language::English) => "Disconnected for being idle",
+        (Error::IdleTimeout, Language::Estonian) => "Ühendus katkestati tegevusetuse tõttu",
+        (Error::MalformedMessage, Language::English) => "Message couldn't be decoded",
+        (Error::MalformedMessage, Language::Estonian) => "Sõnumit ei õnnestunud lugeda",
+        (Error::MissingArgument, Language::English) => "Command is missing an argument",
+        (Error::MissingArgument, Language::Estonian) => "Käsul puudub argument",
+        (Error::InvalidNumber, Language::English) => "Amount has to be a whole number",
+        (Error::InvalidNumber, Language::Estonian) => "Summa peab olema täisarv",
+        (Error::NonPositiveAmount, Language::English) => "Amount has to be a positive number",
+        (Error::NonPositiveAmount, Language::Estonian) => "Summa peab olema positiivne",
+        (Error::UnknownProtocol, Language::English) => "Unknown protocol",
+        (Error::UnknownProtocol, Language::Estonian) => "Tundmatu protokoll",
+        (Error::UnknownLanguage, Language::English) => "Unknown language",
+        (Error::UnknownLanguage, Language::Estonian) => "Tundmatu keel",
+        (Error::InvalidAlias, Language::English) => "Alias has to be a single word that isn't a command",
+        (Error::InvalidAlias, Language::Estonian) => "Alias peab olema üks sõna, mis pole käsk",
+        (Error::InvalidCredentials, Language::English) => "Wrong name or password",
+        (Error::InvalidCredentials, Language::Estonian) => "Vale nimi või parool",
+        (Error::AccountExists, Language::English) => "An account with the name already exists",
+        (Error::AccountExists, Language::Estonian) => "Selle nimega konto on juba olemas",
+        (Error::AccountLocked, Language::English) => "Too many wrong passwords, try again later",
+        (Error::AccountLocked, Language::Estonian) => "Liiga palju valesid paroole, proovi hiljem uuesti",
+        (Error::WeakPassword, Language::English) => "Password has to be at least 8 characters long",
+        (Error::WeakPassword, Language::Estonian) => "Parool peab olema vähemalt 8 märki pikk",
+        (Error::InvalidSession, Language::English) => "Session has expired, log in again",
+        (Error::InvalidSession, Language::Estonian) => "Seanss on aegunud, logi uuesti sisse",
+        (Error::RoundInProgress, Language::English) => "A round is already in progress",
+        (Error::RoundInProgress, Language::Estonian) => "Mänguvoor juba käib",
+        (Error::NoRoundInProgress, Language::English) => "There's no round in progress",
+        (Error::NoRoundInProgress, Language::Estonian) => "Ükski mänguvoor ei käi",
+        (Error::ShoeEmpty, Language::English) => "Shoe ran out of cards",
+        (Error::ShoeEmpty, Language::Estonian) => "Kaardid said otsa",
+        (Error::ActionNotAllowed, Language::English) => "That isn't allowed with this hand",
+        (Error::ActionNotAllowed, Language::Estonian) => "Selle käega pole see lubatud",
+        (Error::NoPreviousBet, Language::English) => "There's no previous bet to repeat",
+        (Error::NoPreviousBet, Language::Estonian) => "Pole varasemat panust, mida korrata",
+        (Error::InsufficientBalance { balance, bet }, Language::English) => {
+            return format!("Not enough balance ({}) to accept the bet ({})", balance, bet)
+        },
+        (Error::InsufficientBalance { balance, bet }, Language::Estonian) => {
+            return format!("Saldost ({}) ei piisa panuseks ({})", balance, bet)
+        },
+        (Error::BalanceLimit, Language::English) => "Balance can't grow that large",
+        (Error::BalanceLimit, Language::Estonian) => "Saldo ei saa nii suureks kasvada",
+    };
+
+    text.to_string()
+}
+
+/// How the command is typed, the keywords stay the same in every language
+pub fn usage(command: Command, language: Language) -> String {
+    let argument = match (command, language) {
+        (Command::Register, Language::English) | (Command::Login, Language::English) => " <name> <password>",
+        (Command::Register, Language::Estonian) | (Command::Login, Language::Estonian) => " <nimi> <parool>",
+        (Command::Resume, _) => " <token>",
+        (Command::Passwd, Language::English) => " <current> <new>",
+        (Command::Passwd, Language::Estonian) => " <praegune> <uus>",
+        (Command::Deposit, Language::English) | (Command::Start, Language::English) => " <int>",
+        (Command::Deposit, Language::Estonian) | (Command::Start, Language::Estonian) => " <arv>",
+        (Command::Protocol, _) => " text|json|binary",
+        (Command::Lang, _) => " en|et",
+        (Command::Help, Language::English) => " [command]",
+        (Command::Help, Language::Estonian) => " [käsk]",
+        _ => "",
+    };
+
+    format!("{}{}", command, argument)
+}
+
+pub fn description(command: Command, language: Language) -> &'static str {
+    match (command, language) {
+        (Command::Exit, Language::English) => "Close the connection",
+        (Command::Exit, Language::Estonian) => "Sulge ühendus",
+        (Command::Register, Language::English) => "Create an account and start a new game",
+        (Command::Register, Language::Estonian) => "Loo konto ja alusta uut mängu",
+        (Command::Login, Language::English) => "Resume the game of the account",
+        (Command::Login, Language::Estonian) => "Jätka konto mängu",
+        (Command::Resume, Language::English) => "Continue the game of an earlier connection",
+        (Command::Resume, Language::Estonian) => "Jätka varasema ühenduse mängu",
+        (Command::Passwd, Language::English) => "Change the password",
+        (Command::Passwd, Language::Estonian) => "Muuda parooli",
+        (Command::Balance, Language::English) => "Show the balance",
+        (Command::Balance, Language::Estonian) => "Näita saldot",
+        (Command::Deposit, Language::English) => "Add to the balance",
+        (Command::Deposit, Language::Estonian) => "Suurenda saldot",
+        (Command::Start, Language::English) => "Start a round with the bet",
+        (Command::Start, Language::Estonian) => "Alusta panusega mänguvooru",
+        (Command::Rebet, Language::English) => "Start a round with the same bet as the last one",
+        (Command::Rebet, Language::Estonian) => "Alusta mänguvooru eelmise panusega",
+        (Command::Hit, Language::English) => "Take another card",
+        (Command::Hit, Language::Estonian) => "Võta veel üks kaart",
+        (Command::Stand, Language::English) => "Keep the hand and let the dealer play",
+        (Command::Stand, Language::Estonian) => "Jää selle käega ja lase diileril mängida",
+        (Command::Double, Language::English) => "Double the bet, take one more card and stand",
+        (Command::Double, Language::Estonian) => "Kahekordista panust, võta üks kaart ja jää",
+        (Command::Surrender, Language::English) => "Give up the hand and get half of the bet back",
+        (Command::Surrender, Language::Estonian) => "Loobu käest ja saa pool panusest tagasi",
+        (Command::Count, Language::English) => "Show the card counts of the shoe",
+        (Command::Count, Language::Estonian) => "Näita kaardikinga loendusi",
+        (Command::Odds, Language::English) => "Show the odds of the dealer's final total",
+        (Command::Odds, Language::Estonian) => "Näita diileri lõppsumma tõenäosusi",
+        (Command::Ping, Language::English) => "Keep the connection from timing out",
+        (Command::Ping, Language::Estonian) => "Hoia ühendus avatuna",
+        (Command::Protocol, Language::English) => "Change the format of the messages",
+        (Command::Protocol, Language::Estonian) => "Muuda sõnumite vormingut",
+        (Command::Lang, Language::English) => "Change the language of the messages",
+        (Command::Lang, Language::Estonian) => "Muuda sõnumite keelt",
+        (Command::Help, Language::English) => "List the commands that can be used right now",
+        (Command::Help, Language::Estonian) => "Näita praegu kasutatavaid käske",
+    }
+}
+
+fn help(commands: &[CommandHelp], language: Language) -> String {
+    let unavailable = match language {
+        Language::English => " (not available right now)",
+        Language::Estonian => " (praegu pole saadaval)",
+    };
+
+    let lines: Vec<String> = commands
+        .iter()
+        .map(|help| format!("{} - {}{}", help.usage, help.description, if help.available { "" } else { unavailable }))
+        .collect();
+
+    lines.join("\r\n")
+}
+
+pub fn outcome(outcome: Outcome, language: Language) -> &'static str {
+    match (outcome, language) {
+        (Outcome::PlayerWon, Language::English) => "Player Won",
+        (Outcome::PlayerWon, Language::Estonian) => "Mängija võitis",
+        (Outcome::DealerWon, Language::English) => "Dealer Won",
+        (Outcome::DealerWon, Language::Estonian) => "Diiler võitis",
+        (Outcome::Draw, Language::English) => "Draw",
+        (Outcome::Draw, Language::Estonian) => "Viik",
+        (Outcome::Bust, Language::English) => "Bust",
+        (Outcome::Bust, Language::Estonian) => "Lõhki",
+        (Outcome::Surrendered, Language::English) => "Surrendered",
+        (Outcome::Surrendered, Language::Estonian) => "Loobutud",
+    }
+}
+
+/// What the player can type next, with the cost of the actions that take from the balance
+pub fn actions(actions: &[LegalAction], language: Language) -> String {
+    let mut choices: Vec<String> = actions
+        .iter()
+        .map(|legal| match legal.cost {
+            0 => format!("\"{}\"", legal.action),
+            cost => format!("\"{}\" ({})", legal.action, cost),
+        })
+        .collect();
+
+    let (prompt, or) = match language {
+        Language::English => ("Type", "or"),
+        Language::Estonian => ("Kirjuta", "või"),
+    };
+
+    match choices.pop() {
+        Some(last) if choices.is_empty() => format!("{} {}", prompt, last),
+        Some(last) => format!("{} {} {} {}", prompt, choices.join(", "), or, last),
+        None => String::new(),
+    }
+}
+
+/// Seconds below a minute, otherwise minutes rounded up so that a short time isn't shown as none
+fn duration(seconds: u64, language: Language) -> String {
+    match (seconds < 60, language) {
+        (true, Language::English) => format!("{} seconds", seconds),
+        (true, Language::Estonian) => format!("{} sekundi", seconds),
+        (false, Language::English) => format!("{} minutes", seconds.div_ceil(60)),
+        (false, Language::Estonian) => format!("{} minuti", seconds.div_ceil(60)),
+    }
+}
+
+/// Human readable form of the message used by the text protocol
+pub fn message(message: &ServerMessage, language: Language) -> String {
+    match language {
+        Language::English => english(message),
+        Language::Estonian => estonian(message),
+    }
+}
+
+fn english(message: &ServerMessage) -> String {
+    match message {
+        ServerMessage::Welcome => String::from("Type \"exit\" any time to stop the process. Type \"register <name> <password>\" to start a new game or \"login <name> <password>\" to resume one."),
+        ServerMessage::LoggedIn { balance } => format!("Successfully logged in. Current balance: {}. Type \"deposit <int>\" to add to your balance or \"start <int>\" to start a new game", balance),
+        ServerMessage::Session { token, expires_in } => format!("Type \"resume {}\" within {} of getting disconnected to continue where you left off", token, duration(*expires_in, Language::English)),
+        ServerMessage::PasswordChanged => String::from("Password changed"),
+        ServerMessage::Balance { balance } => balance.to_string(),
+        ServerMessage::Deposited { amount, .. } => format!("{} deposited to the account", amount),
+        ServerMessage::HandState { dealer, player } => format!("Dealer Hand: {}\r\nPlayer Hand: {}", dealer.total, player.total),
+        ServerMessage::RoundResult { outcome: result, dealer, player, .. } => format!("{}! Dealer Hand: {}\r\nPlayer Hand: {}\r\nType \"start <int>\" to start a new game", outcome(*result, Language::English), dealer.total, player.total),
+        ServerMessage::Count { counts } => {
+            let lines: Vec<String> = counts
+                .iter()
+                .map(|count| format!("{}: running count {}, true count {:.1}", count.system, count.running, count.true_count))
+                .collect();
+
+            lines.join("\r\n")
+        },
+        ServerMessage::Odds { upcard, totals, bust } => {
+            let mut text = format!("Dealer's final total with {} showing:\r\n", upcard);
+            for total in totals.iter() {
+                text += &format!("{}: {:.1}%\r\n", total.total, total.probability * 100.0);
+            }
+
+            text + &format!("Bust: {:.1}%", bust * 100.0)
+        },
+        ServerMessage::ProtocolChanged { protocol } => format!("Protocol set to {}", protocol),
+        ServerMessage::LanguageChanged { .. } => String::from("Language set to English"),
+        ServerMessage::Help { commands } => help(commands, Language::English),
+        ServerMessage::Pong => String::from("pong"),
+        ServerMessage::Shuffled => String::from("The shoe was reshuffled, counting starts over"),
+        ServerMessage::Error { text, .. } => text.clone(),
+    }
+}
+
+fn estonian(message: &ServerMessage) -> String {
+    match message {
+        ServerMessage::Welcome => String::from("Kirjuta \"exit\", et igal ajal lõpetada. Kirjuta \"register <nimi> <parool>\", et alustada uut mängu, või \"login <nimi> <parool>\", et mängu jätkata."),
+        ServerMessage::LoggedIn { balance } => format!("Sisselogimine õnnestus. Praegune saldo: {}. Kirjuta \"deposit <arv>\", et saldot suurendada, või \"start <arv>\", et alustada uut mängu", balance),
+        ServerMessage::Session { token, expires_in } => format!("Kirjuta \"resume {}\" {} jooksul pärast ühenduse katkemist, et pooleli jäänud kohast jätkata", token, duration(*expires_in, Language::Estonian)),
+        ServerMessage::PasswordChanged => String::from("Parool on muudetud"),
+        ServerMessage::Balance { balance } => balance.to_string(),
+        ServerMessage::Deposited { amount, .. } => format!("Kontole lisati {}", amount),
+        ServerMessage::HandState { dealer, player } => format!("Diileri käsi: {}\r\nMängija käsi: {}", dealer.total, player.total),
+        ServerMessage::RoundResult { outcome: result, dealer, player, .. } => format!("{}! Diileri käsi: {}\r\nMängija käsi: {}\r\nKirjuta \"start <arv>\", et alustada uut mängu", outcome(*result, Language::Estonian), dealer.total, player.total),
+        ServerMessage::Count { counts } => {
+            let lines: Vec<String> = counts
+                .iter()
+                .map(|count| format!("{}: jooksev loendus {}, tegelik loendus {:.1}", count.system, count.running, count.true_count))
+                .collect();
+
+            lines.join("\r\n")
+        },
+        ServerMessage::Odds { upcard, totals, bust } => {
+            let mut text = format!("Diileri lõppsumma, kui avatud kaart on {}:\r\n", upcard);
+            for total in totals.iter() {
+                text += &format!("{}: {:.1}%\r\n", total.total, total.probability * 100.0);
+            }
+
+            text + &format!("Lõhki: {:.1}%", bust * 100.0)
+        },
+        ServerMessage::ProtocolChanged { protocol } => format!("Protokoll on nüüd {}", protocol),
+        ServerMessage::LanguageChanged { .. } => String::from("Keel on nüüd eesti"),
+        ServerMessage::Help { commands } => help(commands, Language::Estonian),
+        ServerMessage::Pong => String::from("pong"),
+        ServerMessage::Shuffled => String::from("Kaardid segati uuesti, loendamine algab otsast"),
+        ServerMessage::Error { text, .. } => text.clone(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::server_message::Hand;
+
+    #[test]
+    fn language_parsing() {
+        assert_eq!(Language::Estonian, "ET".parse().unwrap());
+        assert_eq!(Language::English, " en ".parse().unwrap());
+        assert!("fi".parse::<Language>().is_err());
+        assert_eq!("et", Language::Estonian.to_string());
+    }
+
+    #[test]
+    fn templates() {
+        let result = ServerMessage::RoundResult {
+            outcome: Outcome::DealerWon,
+            dealer: Hand { cards: vec!["TH".to_string(), "9S".to_string()], total: 19 },
+            player: Hand { cards: vec!["8D".to_string(), "9C".to_string()], total: 17 },
+            balance: 90,
+        };
+
+        assert_eq!(
+            "Diiler võitis! Diileri käsi: 19\r\nMängija käsi: 17\r\nKirjuta \"start <arv>\", et alustada uut mängu",
+            message(&result, Language::Estonian)
+        );
+        assert_eq!(result.to_string(), message(&result, Language::English));
+        assert_eq!("Kontole lisati 5", message(&ServerMessage::Deposited { amount: 5, balance: 5 }, Language::Estonian));
+
+        let session = |expires_in| message(&ServerMessage::Session { token: "ab".to_string(), expires_in }, Language::English);
+        assert!(session(30).contains("within 30 seconds"));
+        assert!(session(90).contains("within 2 minutes"));
+        assert!(session(600).contains("within 10 minutes"));
+    }
+}
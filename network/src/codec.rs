@@ -0,0 +1,247 @@
+use crate::read::{Error, ErrorKind};
+use std::convert::TryFrom;
+use std::io::{self, prelude::*};
+
+/// Way the messages are split into frames on the stream
+pub trait Codec: Send + Sync {
+    fn write_frame(&self, writer: &mut dyn Write, frame: &[u8]) -> io::Result<()>;
+
+    /// Reads a frame that is at most `max_size` bytes long. A frame that is too large isn't consumed from the
+    /// reader, so the stream can't be used for anything else afterwards
+    fn read_frame(&self, reader: &mut dyn BufRead, max_size: u64) -> Result<Vec<u8>, Error>;
+}
+
+/// Length in ASCII digits followed by "\r\n" and the message, easy to type and read by hand
+pub struct TextCodec;
+
+/// Length as u32 big-endian followed by the message
+pub struct BinaryCodec;
+
+impl TextCodec {
+    /// Longest length prefix line, enough for any u64 followed by "\r\n"
+    const MAX_LENGTH_LINE: u64 = 22;
+}
+
+impl Codec for TextCodec {
+    fn write_frame(&self, writer: &mut dyn Write, frame: &[u8]) -> io::Result<()> {
+        debug!("Writing {} bytes to stream", frame.len());
+        let mut bytes = format!("{}\r\n", frame.len()).into_bytes();
+        bytes.extend_from_slice(frame);
+        writer.write_all(&bytes)
+    }
+
+    fn read_frame(&self, reader: &mut dyn BufRead, max_size: u64) -> Result<Vec<u8>, Error> {
+        let mut line = Vec::new();
+
+        debug!("Waiting for incoming message");
+        match (&mut *reader).take(Self::MAX_LENGTH_LINE).read_until(b'\n', &mut line) {
+            Ok(0) => return Err(Error::from(ErrorKind::ConnectionLost)),
+            Ok(_) => (),
+            Err(e) => return Err(interrupted(e, !line.is_empty())),
+        }
+
+        debug!("Received {:?} length prefix", String::from_utf8_lossy(&line));
+        if line.last() != Some(&b'\n') {
+            // Either the stream ended or the line is longer than any valid length could be
+            let kind = if (line.len() as u64) < Self::MAX_LENGTH_LINE {
+                ErrorKind::TruncatedFrame
+            } else {
+                ErrorKind::InvalidBytesRead
+            };
+            return Err(Error::from(kind));
+        }
+
+        let size = String::from_utf8_lossy(&line).trim_end().parse::<u64>()?;
+
+        read_body(reader, size, max_size)
+    }
+}
+
+impl Codec for BinaryCodec {
+    fn write_frame(&self, writer: &mut dyn Write, frame: &[u8]) -> io::Result<()> {
+        let size = u32::try_from(frame.len())
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "message is too large for binary framing"))?;
+
+        debug!("Writing {} bytes to stream", size);
+        let mut bytes = size.to_be_bytes().to_vec();
+        bytes.extend_from_slice(frame);
+        writer.write_all(&bytes)
+    }
+
+    fn read_frame(&self, reader: &mut dyn BufRead, max_size: u64) -> Result<Vec<u8>, Error> {
+        let mut prefix = Vec::with_capacity(4);
+
+        debug!("Waiting for incoming message");
+        match (&mut *reader).take(4).read_to_end(&mut prefix) {
+            Ok(0) => return Err(Error::from(ErrorKind::ConnectionLost)),
+            Ok(4) => (),
+            Ok(_) => return Err(Error::from(ErrorKind::TruncatedFrame)),
+            Err(e) => return Err(interrupted(e, !prefix.is_empty())),
+        }
+
+        let size = u32::from_be_bytes([prefix[0], prefix[1], prefix[2], prefix[3]]) as u64;
+
+        read_body(reader, size, max_size)
+    }
+}
+
+/// Timing out in the middle of a frame loses the part that was already read, so the stream can't be used anymore
+fn interrupted(error: io::Error, partial: bool) -> Error {
+    let error = Error::from(error);
+    if partial && error.kind() == ErrorKind::TimedOut {
+        return Error::from(ErrorKind::TruncatedFrame);
+    }
+
+    error
+}
+
+fn read_body(reader: &mut dyn BufRead, size: u64, max_size: u64) -> Result<Vec<u8>, Error> {
+    debug!("Message is {} bytes long", size);
+    if size > max_size {
+        return Err(Error::from(ErrorKind::FrameTooLarge));
+    }
+
+    let mut buffer = Vec::with_capacity(size as usize);
+    reader.take(size).read_to_end(&mut buffer).map_err(|e| interrupted(e, true))?;
+    if (buffer.len() as u64) < size {
+        return Err(Error::from(ErrorKind::TruncatedFrame));
+    }
+
+    Ok(buffer)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    fn encode(codec: &dyn Codec, frames: &[&[u8]]) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        for frame in frames {
+            codec.write_frame(&mut bytes, frame).unwrap();
+        }
+
+        bytes
+    }
+
+    #[test]
+    fn text_framing() {
+        assert_eq!(b"3\r\nhit0\r\n".to_vec(), encode(&TextCodec, &[b"hit", b""]));
+    }
+
+    #[test]
+    fn binary_framing() {
+        assert_eq!(b"\x00\x00\x00\x03hit\x00\x00\x00\x00".to_vec(), encode(&BinaryCodec, &[b"hit", b""]));
+    }
+
+    #[test]
+    fn binary_roundtrip() {
+        let frames: [&[u8]; 3] = [b"\x00\xff\r\n", b"", &[7; 300]];
+        let mut reader = io::Cursor::new(encode(&BinaryCodec, &frames));
+
+        for frame in frames.iter() {
+            assert_eq!(frame.to_vec(), BinaryCodec.read_frame(&mut reader, 1024).unwrap());
+        }
+
+        let error = BinaryCodec.read_frame(&mut reader, 1024).unwrap_err();
+        assert_eq!(ErrorKind::ConnectionLost, error.kind());
+    }
+
+    #[test]
+    fn binary_errors() {
+        let mut reader = io::Cursor::new(b"\x00\x00".to_vec());
+        assert_eq!(ErrorKind::TruncatedFrame, BinaryCodec.read_frame(&mut reader, 1024).unwrap_err().kind());
+
+        let mut reader = io::Cursor::new(b"\x00\x00\x00\x05hit".to_vec());
+        assert_eq!(ErrorKind::TruncatedFrame, BinaryCodec.read_frame(&mut reader, 1024).unwrap_err().kind());
+
+        let mut reader = io::Cursor::new(b"\xff\xff\xff\xff".to_vec());
+        assert_eq!(ErrorKind::FrameTooLarge, BinaryCodec.read_frame(&mut reader, 1024).unwrap_err().kind());
+    }
+
+    /// Gives out the bytes and then times out like a socket with a read timeout
+    struct SlowReader {
+        bytes: io::Cursor<Vec<u8>>,
+    }
+
+    impl Read for SlowReader {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            match self.bytes.read(buf)? {
+                0 => Err(io::Error::new(io::ErrorKind::WouldBlock, "timed out")),
+                read => Ok(read),
+            }
+        }
+    }
+
+    #[test]
+    fn timeouts() {
+        let codecs: [&dyn Codec; 2] = [&TextCodec, &BinaryCodec];
+
+        for codec in codecs.iter() {
+            let mut reader = io::BufReader::new(SlowReader { bytes: io::Cursor::new(Vec::new()) });
+            assert_eq!(ErrorKind::TimedOut, codec.read_frame(&mut reader, 1024).unwrap_err().kind());
+
+            // Half of the frame is gone once the read times out
+            let frame = encode(*codec, &[b"deposit 10"]);
+            let mut reader = io::BufReader::new(SlowReader { bytes: io::Cursor::new(frame[..6].to_vec()) });
+            assert_eq!(ErrorKind::TruncatedFrame, codec.read_frame(&mut reader, 1024).unwrap_err().kind());
+
+            let mut reader = io::BufReader::new(SlowReader { bytes: io::Cursor::new(frame[..2].to_vec()) });
+            assert_eq!(ErrorKind::TruncatedFrame, codec.read_frame(&mut reader, 1024).unwrap_err().kind());
+        }
+    }
+
+    #[test]
+    fn fuzz_binary_random_bytes() {
+        let mut rng = StdRng::seed_from_u64(2024);
+
+        for _ in 0..10_000 {
+            let length = rng.gen_range(0, 64);
+            let mut bytes: Vec<u8> = (0..length).map(|_| rng.gen()).collect();
+            // Small length prefixes so that some of the inputs contain whole frames
+            for byte in bytes.iter_mut() {
+                if rng.gen_bool(0.5) {
+                    *byte = 0;
+                }
+            }
+
+            let mut reader = io::Cursor::new(bytes.clone());
+            while let Ok(frame) = BinaryCodec.read_frame(&mut reader, 32) {
+                assert!(frame.len() <= 32, "{:?}", bytes);
+            }
+        }
+    }
+
+    #[test]
+    fn fuzz_roundtrip_both_codecs() {
+        let mut rng = StdRng::seed_from_u64(2024);
+        let codecs: [&dyn Codec; 2] = [&TextCodec, &BinaryCodec];
+
+        for _ in 0..1_000 {
+            let frames: Vec<Vec<u8>> = (0..rng.gen_range(1, 5))
+                .map(|_| (0..rng.gen_range(0, 200)).map(|_| rng.gen()).collect())
+                .collect();
+
+            for codec in codecs.iter() {
+                let bytes = encode(*codec, &frames.iter().map(|frame| &frame[..]).collect::<Vec<_>>());
+
+                // Cutting the stream short anywhere must give a truncated frame or connection lost, never garbage
+                let cut = rng.gen_range(0, bytes.len() + 1);
+                let mut reader = io::Cursor::new(bytes[..cut].to_vec());
+                for frame in frames.iter() {
+                    match codec.read_frame(&mut reader, 1024) {
+                        Ok(read) => assert_eq!(*frame, read),
+                        Err(e) => {
+                            assert!(
+                                e.kind() == ErrorKind::TruncatedFrame || e.kind() == ErrorKind::ConnectionLost,
+                                "{}",
+                                e
+                            );
+                            break;
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
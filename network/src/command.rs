@@ -0,0 +1,136 @@
+use crate::error::Error;
+use crate::{InvalidMessageError, Message};
+use serde::{Deserialize, Serialize};
+use std::{fmt, str::FromStr};
+
+/// Keyword of a client message, what `help` explains
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone, Copy, Hash)]
+#[serde(rename_all = "lowercase")]
+pub enum Command {
+    Exit,
+    Register,
+    Login,
+    Passwd,
+    Resume,
+    Balance,
+    Deposit,
+    Start,
+    Rebet,
+    Hit,
+    Stand,
+    Double,
+    Surrender,
+    Count,
+    Odds,
+    Ping,
+    Protocol,
+    Lang,
+    Help,
+}
+
+impl Command {
+    /// Every command in the order `help` lists them
+    pub const ALL: [Command; 19] = [
+        Self::Register,
+        Self::Login,
+        Self::Passwd,
+        Self::Resume,
+        Self::Balance,
+        Self::Deposit,
+        Self::Start,
+        Self::Rebet,
+        Self::Hit,
+        Self::Stand,
+        Self::Double,
+        Self::Surrender,
+        Self::Count,
+        Self::Odds,
+        Self::Lang,
+        Self::Protocol,
+        Self::Ping,
+        Self::Help,
+        Self::Exit,
+    ];
+}
+
+impl FromStr for Command {
+    type Err = InvalidMessageError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .iter()
+            .find(|command| command.to_string() == s.trim().to_lowercase())
+            .copied()
+            .ok_or_else(|| InvalidMessageError { error: Error::UnknownCommand, message: s.to_string() })
+    }
+}
+
+impl fmt::Display for Command {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let text = match self {
+            Self::Exit => "exit",
+            Self::Register => "register",
+            Self::Login => "login",
+            Self::Passwd => "passwd",
+            Self::Resume => "resume",
+            Self::Balance => "balance",
+            Self::Deposit => "deposit",
+            Self::Start => "start",
+            Self::Rebet => "rebet",
+            Self::Hit => "hit",
+            Self::Stand => "stand",
+            Self::Double => "double",
+            Self::Surrender => "surrender",
+            Self::Count => "count",
+            Self::Odds => "odds",
+            Self::Ping => "ping",
+            Self::Protocol => "protocol",
+            Self::Lang => "lang",
+            Self::Help => "help",
+        };
+
+        write!(f, "{}", text)
+    }
+}
+
+impl Message {
+    pub fn command(&self) -> Command {
+        match self {
+            Self::Exit => Command::Exit,
+            Self::Register { .. } => Command::Register,
+            Self::Login { .. } => Command::Login,
+            Self::Passwd { .. } => Command::Passwd,
+            Self::Resume(_) => Command::Resume,
+            Self::Balance => Command::Balance,
+            Self::Deposit(_) => Command::Deposit,
+            Self::Start(_) => Command::Start,
+            Self::Rebet => Command::Rebet,
+            Self::Hit => Command::Hit,
+            Self::Stand => Command::Stand,
+            Self::Double => Command::Double,
+            Self::Surrender => Command::Surrender,
+            Self::Count => Command::Count,
+            Self::Odds => Command::Odds,
+            Self::Ping => Command::Ping,
+            Self::Protocol(_) => Command::Protocol,
+            Self::Lang(_) => Command::Lang,
+            Self::Help(_) => Command::Help,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keywords() {
+        for command in Command::ALL.iter() {
+            assert_eq!(*command, command.to_string().parse().unwrap());
+        }
+
+        assert_eq!(Command::Hit, " HIT ".parse().unwrap());
+        assert_eq!(Error::UnknownCommand, "fly".parse::<Command>().unwrap_err().error());
+        assert_eq!(Command::Deposit, Message::Deposit(5).command());
+    }
+}
@@ -0,0 +1,317 @@
+use crate::alias::Aliases;
+use crate::error::Error;
+use crate::catalog::{self, Language};
+use crate::server_message::LegalAction;
+use crate::{InvalidMessageError, Message, Protocol, ServerMessage};
+use serde::{Deserialize, Serialize};
+use std::{fmt, str::FromStr};
+
+/// Client message with an optional id that the server echoes in its reply, so that commands can be pipelined
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+pub struct Request {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<u32>,
+    #[serde(flatten)]
+    pub message: Message,
+}
+
+impl Request {
+    pub fn new(id: Option<u32>, message: Message) -> Self {
+        Self { id, message }
+    }
+
+    pub fn from_json(s: &str) -> Result<Self, InvalidMessageError> {
+        serde_json::from_str(s).map_err(|e| InvalidMessageError { error: Error::MalformedMessage, message: e.to_string() })
+    }
+
+    pub fn to_json(&self) -> String {
+        // Serializing plain enums and structs to a string can't fail
+        serde_json::to_string(self).unwrap()
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, InvalidMessageError> {
+        rmp_serde::from_slice(bytes).map_err(|e| InvalidMessageError { error: Error::MalformedMessage, message: e.to_string() })
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        // Serializing plain enums and structs to a vector can't fail
+        rmp_serde::to_vec_named(self).unwrap()
+    }
+
+    pub fn encode(&self, protocol: Protocol) -> Vec<u8> {
+        match protocol {
+            Protocol::Text => self.to_string().into_bytes(),
+            Protocol::Json => self.to_json().into_bytes(),
+            Protocol::Binary => self.to_bytes(),
+        }
+    }
+
+    pub fn decode(bytes: &[u8], protocol: Protocol) -> Result<Self, InvalidMessageError> {
+        Self::decode_with(bytes, protocol, &Aliases::new())
+    }
+
+    /// Decodes the request with the aliases applied to text messages, JSON and binary ones don't have any
+    pub fn decode_with(bytes: &[u8], protocol: Protocol, aliases: &Aliases) -> Result<Self, InvalidMessageError> {
+        match protocol {
+            Protocol::Text => Self::parse_with(&String::from_utf8_lossy(bytes), aliases),
+            Protocol::Json => Self::from_json(&String::from_utf8_lossy(bytes)),
+            Protocol::Binary => Self::from_bytes(bytes),
+        }
+    }
+
+    /// Id of the request even when its message can't be decoded, so that the error can still be a reply to it
+    pub fn decode_id(bytes: &[u8], protocol: Protocol) -> Option<u32> {
+        #[derive(Deserialize)]
+        struct Id {
+            #[serde(default)]
+            id: Option<u32>,
+        }
+
+        match protocol {
+            Protocol::Text => String::from_utf8_lossy(bytes).trim().strip_prefix('#')?.split_whitespace().next()?.parse().ok(),
+            Protocol::Json => serde_json::from_slice::<Id>(bytes).ok()?.id,
+            Protocol::Binary => rmp_serde::from_slice::<Id>(bytes).ok()?.id,
+        }
+    }
+
+    /// Text requests can start with "#<id>", for example "#3 hit"
+    pub fn parse_with(s: &str, aliases: &Aliases) -> Result<Self, InvalidMessageError> {
+        let s = s.trim();
+        match s.strip_prefix('#') {
+            Some(rest) => {
+                let mut split = rest.splitn(2, char::is_whitespace);
+                let id = split
+                    .next()
+                    .and_then(|id| id.parse::<u32>().ok())
+                    .ok_or_else(|| InvalidMessageError { error: Error::MalformedMessage, message: String::from("Unable to parse request id") })?;
+
+                Ok(Self::new(Some(id), aliases.parse(split.next().unwrap_or(""))?))
+            },
+            None => Ok(Self::new(None, aliases.parse(s)?)),
+        }
+    }
+}
+
+impl FromStr for Request {
+    type Err = InvalidMessageError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::parse_with(s, &Aliases::new())
+    }
+}
+
+impl fmt::Display for Request {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.id {
+            Some(id) => write!(f, "#{} {}", id, self.message),
+            None => write!(f, "{}", self.message),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum Kind {
+    /// Answer to a request of the client
+    Reply,
+    /// Something that happened without the client asking, like the shoe being reshuffled
+    Event,
+}
+
+/// Server message telling whether it answers a request and which one
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct Response {
+    pub kind: Kind,
+    /// Id of the request that is replied to, if the request had one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<u32>,
+    #[serde(flatten)]
+    pub message: ServerMessage,
+    /// What the player can do next in the round, so that clients only offer valid choices
+    #[serde(default)]
+    pub actions: Vec<LegalAction>,
+}
+
+impl Response {
+    pub fn reply(id: Option<u32>, message: ServerMessage) -> Self {
+        Self { kind: Kind::Reply, id, message, actions: Vec::new() }
+    }
+
+    pub fn event(message: ServerMessage) -> Self {
+        Self { kind: Kind::Event, id: None, message, actions: Vec::new() }
+    }
+
+    pub fn with_actions(mut self, actions: Vec<LegalAction>) -> Self {
+        self.actions = actions;
+        self
+    }
+
+    pub fn from_json(s: &str) -> Result<Self, InvalidMessageError> {
+        serde_json::from_str(s).map_err(|e| InvalidMessageError { error: Error::MalformedMessage, message: e.to_string() })
+    }
+
+    pub fn to_json(&self) -> String {
+        // Serializing plain enums and structs to a string can't fail
+        serde_json::to_string(self).unwrap()
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, InvalidMessageError> {
+        rmp_serde::from_slice(bytes).map_err(|e| InvalidMessageError { error: Error::MalformedMessage, message: e.to_string() })
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        // Serializing plain enums and structs to a vector can't fail
+        rmp_serde::to_vec_named(self).unwrap()
+    }
+
+    pub fn encode(&self, protocol: Protocol) -> Vec<u8> {
+        self.encode_in(protocol, Language::English)
+    }
+
+    /// Encodes the message with the text protocol written in the language, the other formats aren't affected
+    pub fn encode_in(&self, protocol: Protocol, language: Language) -> Vec<u8> {
+        match protocol {
+            Protocol::Text => self.text(language).into_bytes(),
+            Protocol::Json => self.to_json().into_bytes(),
+            Protocol::Binary => self.to_bytes(),
+        }
+    }
+
+    /// Human readable form with the "#<id>" prefix of replies to text requests
+    /// Hands of a round are followed by the actions the player can take
+    pub fn text(&self, language: Language) -> String {
+        let mut text = self.message.text(language);
+        if let (ServerMessage::HandState { .. }, false) = (&self.message, self.actions.is_empty()) {
+            text = format!("{}\r\n{}", text, catalog::actions(&self.actions, language));
+        }
+
+        match self.id {
+            Some(id) => format!("#{} {}", id, text),
+            None => text,
+        }
+    }
+
+    /// Decodes the bytes of a frame, text protocol messages are only meant for humans and can't be decoded
+    pub fn decode(bytes: &[u8], protocol: Protocol) -> Result<Self, InvalidMessageError> {
+        match protocol {
+            Protocol::Text => Err(InvalidMessageError { error: Error::MalformedMessage, message: String::from_utf8_lossy(bytes).to_string() }),
+            Protocol::Json => Self::from_json(&String::from_utf8_lossy(bytes)),
+            Protocol::Binary => Self::from_bytes(bytes),
+        }
+    }
+}
+
+/// Replies to text requests with an id start with the same "#<id>"
+impl fmt::Display for Response {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.text(Language::English))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::server_message::{Action, Hand};
+
+    #[test]
+    fn text_request() {
+        assert_eq!(Request::new(None, Message::Hit), "hit".parse().unwrap());
+        assert_eq!(Request::new(Some(3), Message::Start(10)), "#3 start 10".parse().unwrap());
+        assert_eq!("#3 start 10", Request::new(Some(3), Message::Start(10)).to_string());
+        assert!("#x hit".parse::<Request>().is_err());
+        assert!("#3".parse::<Request>().is_err());
+        assert!("#3 h".parse::<Request>().is_err());
+        assert_eq!(Request::new(Some(3), Message::Hit), Request::parse_with("#3 h", &Aliases::default()).unwrap());
+    }
+
+    #[test]
+    fn id_of_invalid_request() {
+        assert_eq!(Some(3), Request::decode_id(b"#3 fly", Protocol::Text));
+        assert_eq!(None, Request::decode_id(b"fly", Protocol::Text));
+        assert_eq!(Some(7), Request::decode_id(br#"{"id":7,"command":"fly"}"#, Protocol::Json));
+        assert_eq!(None, Request::decode_id(br#"{"command":"fly"}"#, Protocol::Json));
+        assert_eq!(None, Request::decode_id(b"not json", Protocol::Json));
+
+        let request = Request::new(Some(9), Message::Deposit(5)).encode(Protocol::Binary);
+        assert_eq!(Some(9), Request::decode_id(&request, Protocol::Binary));
+    }
+
+    #[test]
+    fn json_request() {
+        assert_eq!(Request::new(Some(7), Message::Hit), Request::from_json(r#"{"id":7,"command":"hit"}"#).unwrap());
+        // Clients that don't use ids send plain messages
+        assert_eq!(
+            Request::new(None, Message::Deposit(5)),
+            Request::from_json(r#"{"command":"deposit","value":5}"#).unwrap()
+        );
+        assert_eq!(r#"{"id":7,"command":"hit"}"#, Request::new(Some(7), Message::Hit).to_json());
+    }
+
+    #[test]
+    fn response_format() {
+        let reply = Response::reply(Some(7), ServerMessage::Balance { balance: 5 });
+        assert_eq!(r#"{"kind":"reply","id":7,"type":"balance","balance":5,"actions":[]}"#, reply.to_json());
+        assert_eq!("#7 5", reply.to_string());
+
+        let event = Response::event(ServerMessage::Shuffled);
+        assert_eq!(r#"{"kind":"event","type":"shuffled","actions":[]}"#, event.to_json());
+
+        let actions = vec![LegalAction { action: Action::Hit, cost: 0 }, LegalAction { action: Action::Double, cost: 10 }];
+        let state = Response::reply(None, ServerMessage::Pong).with_actions(actions);
+        assert_eq!(
+            r#"{"kind":"reply","type":"pong","actions":[{"action":"hit","cost":0},{"action":"double","cost":10}]}"#,
+            state.to_json()
+        );
+        assert_eq!("pong", state.to_string());
+
+        // Text clients are told which actions they can take and what they cost
+        let hands = ServerMessage::HandState {
+            dealer: Hand { cards: vec!["TH".to_string()], total: 10 },
+            player: Hand { cards: vec!["8D".to_string(), "3C".to_string()], total: 11 },
+        };
+        let actions = vec![
+            LegalAction { action: Action::Hit, cost: 0 },
+            LegalAction { action: Action::Stand, cost: 0 },
+            LegalAction { action: Action::Double, cost: 10 },
+            LegalAction { action: Action::Surrender, cost: 5 },
+        ];
+        let state = Response::reply(Some(4), hands).with_actions(actions);
+        assert_eq!(
+            "#4 Dealer Hand: 10\r\nPlayer Hand: 11\r\nType \"hit\", \"stand\", \"double\" (10) or \"surrender\" (5)",
+            state.to_string()
+        );
+        assert!(state.text(Language::Estonian).ends_with("Kirjuta \"hit\", \"stand\", \"double\" (10) või \"surrender\" (5)"));
+
+        // Clients that don't know about responses still understand the message
+        assert_eq!(ServerMessage::Balance { balance: 5 }, ServerMessage::from_json(&reply.to_json()).unwrap());
+    }
+
+    #[test]
+    fn roundtrip() {
+        let requests = [
+            Request::new(Some(1), Message::Login { name: "bob".to_string(), password: "secret".to_string() }),
+            Request::new(None, Message::Stand),
+            Request::new(Some(u32::MAX), Message::Protocol(Protocol::Json)),
+        ];
+        let responses = [
+            Response::reply(Some(1), ServerMessage::LoggedIn { balance: 0 }),
+            Response::reply(None, ServerMessage::error(Error::UnknownCommand, Language::English)),
+            Response::event(ServerMessage::Welcome),
+            Response::reply(Some(2), ServerMessage::Pong).with_actions(vec![LegalAction { action: Action::Surrender, cost: 5 }]),
+        ];
+
+        for protocol in [Protocol::Json, Protocol::Binary].iter() {
+            for request in requests.iter() {
+                assert_eq!(*request, Request::decode(&request.encode(*protocol), *protocol).unwrap());
+            }
+
+            for response in responses.iter() {
+                assert_eq!(*response, Response::decode(&response.encode(*protocol), *protocol).unwrap());
+            }
+        }
+
+        for request in requests.iter() {
+            assert_eq!(*request, Request::decode(&request.encode(Protocol::Text), Protocol::Text).unwrap());
+        }
+    }
+}
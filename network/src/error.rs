@@ -0,0 +1,140 @@
+use crate::catalog::{self, Language};
+use std::{error, fmt};
+
+/// Everything that can go wrong with a command. Codes never change meaning, so that clients can rely on them:
+/// 1-9 are the original codes, 10-19 parse errors, 20-29 auth errors, 30-39 game rules and 40-49 balance errors
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum Error {
+    /// Command couldn't be parsed
+    UnknownCommand,
+    /// Command needs the connection to be logged in
+    NotLoggedIn,
+    /// Something went wrong on the server side
+    ServerError,
+    /// Message framing was broken, the connection is closed after this
+    InvalidFrame,
+    /// Nothing was heard from the client for too long, the connection is closed after this
+    IdleTimeout,
+    /// JSON or MessagePack that isn't any known message
+    MalformedMessage,
+    MissingArgument,
+    InvalidNumber,
+    /// Amounts of zero aren't allowed
+    NonPositiveAmount,
+    UnknownProtocol,
+    UnknownLanguage,
+    /// Alias is a command itself or isn't a single word
+    InvalidAlias,
+    /// Name or password is wrong, which one isn't told
+    InvalidCredentials,
+    AccountExists,
+    /// Too many wrong passwords in a row, logging in is refused for a while
+    AccountLocked,
+    /// Password is too short to be accepted
+    WeakPassword,
+    /// Session token is unknown or has expired
+    InvalidSession,
+    RoundInProgress,
+    NoRoundInProgress,
+    /// Shoe ran out of cards and couldn't be refilled
+    ShoeEmpty,
+    /// Round is on but the hand doesn't allow the action, e.g. doubling after hitting
+    ActionNotAllowed,
+    /// Bet can't be repeated before any round has been played
+    NoPreviousBet,
+    InsufficientBalance { balance: u32, bet: u32 },
+    /// Deposit would make the balance larger than it can be
+    BalanceLimit,
+}
+
+impl Error {
+    pub fn code(&self) -> u16 {
+        match self {
+            Self::UnknownCommand => 1,
+            Self::NotLoggedIn => 2,
+            // 3 was the code of every game error before they got their own codes
+            Self::ServerError => 4,
+            Self::InvalidFrame => 5,
+            Self::IdleTimeout => 6,
+            Self::MalformedMessage => 10,
+            Self::MissingArgument => 11,
+            Self::InvalidNumber => 12,
+            Self::NonPositiveAmount => 13,
+            Self::UnknownProtocol => 14,
+            Self::UnknownLanguage => 15,
+            Self::InvalidAlias => 16,
+            Self::InvalidCredentials => 20,
+            Self::AccountExists => 21,
+            Self::AccountLocked => 22,
+            Self::WeakPassword => 23,
+            Self::InvalidSession => 24,
+            Self::RoundInProgress => 30,
+            Self::NoRoundInProgress => 31,
+            Self::ShoeEmpty => 32,
+            Self::ActionNotAllowed => 33,
+            Self::NoPreviousBet => 34,
+            Self::InsufficientBalance { .. } => 40,
+            Self::BalanceLimit => 41,
+        }
+    }
+
+    /// Text of the error in the language, see `catalog`
+    pub fn text(&self, language: Language) -> String {
+        catalog::error(self, language)
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.text(Language::English))
+    }
+}
+
+impl error::Error for Error {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn codes_are_unique() {
+        let errors = [
+            Error::UnknownCommand,
+            Error::NotLoggedIn,
+            Error::ServerError,
+            Error::InvalidFrame,
+            Error::IdleTimeout,
+            Error::MalformedMessage,
+            Error::MissingArgument,
+            Error::InvalidNumber,
+            Error::NonPositiveAmount,
+            Error::UnknownProtocol,
+            Error::UnknownLanguage,
+            Error::InvalidAlias,
+            Error::InvalidCredentials,
+            Error::AccountExists,
+            Error::AccountLocked,
+            Error::WeakPassword,
+            Error::InvalidSession,
+            Error::RoundInProgress,
+            Error::NoRoundInProgress,
+            Error::ShoeEmpty,
+            Error::ActionNotAllowed,
+            Error::NoPreviousBet,
+            Error::InsufficientBalance { balance: 0, bet: 0 },
+            Error::BalanceLimit,
+        ];
+
+        let mut codes: Vec<u16> = errors.iter().map(Error::code).collect();
+        codes.sort_unstable();
+        codes.dedup();
+        assert_eq!(errors.len(), codes.len());
+    }
+
+    #[test]
+    fn text() {
+        let error = Error::InsufficientBalance { balance: 5, bet: 10 };
+        assert_eq!("Not enough balance (5) to accept the bet (10)", error.to_string());
+        assert_eq!("Saldost (5) ei piisa panuseks (10)", error.text(Language::Estonian));
+    }
+}
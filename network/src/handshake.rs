@@ -0,0 +1,184 @@
+use crate::error::Error;
+use crate::InvalidMessageError;
+use std::{fmt, str::FromStr};
+
+/// Version of the protocol this crate implements
+pub const VERSION: u16 = 1;
+/// Oldest version of the protocol that is still understood
+pub const MINIMUM_VERSION: u16 = 1;
+
+/// Optional features of the protocol
+#[derive(PartialEq, Debug, Clone)]
+pub enum Capability {
+    /// JSON messages instead of text
+    Json,
+    /// MessagePack messages in binary frames
+    Binary,
+    /// Game variants other than the classic rules
+    Variants,
+    /// Side bets next to the main bet
+    SideBets,
+    /// Feature of a newer version, kept so that it can be ignored instead of rejecting the peer
+    Unknown(String),
+}
+
+impl FromStr for Capability {
+    type Err = InvalidMessageError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let capability = match s.to_lowercase().as_ref() {
+            "json" => Self::Json,
+            "binary" => Self::Binary,
+            "variants" => Self::Variants,
+            "side_bets" => Self::SideBets,
+            other => Self::Unknown(other.to_string()),
+        };
+
+        Ok(capability)
+    }
+}
+
+impl fmt::Display for Capability {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Json => write!(f, "json"),
+            Self::Binary => write!(f, "binary"),
+            Self::Variants => write!(f, "variants"),
+            Self::SideBets => write!(f, "side_bets"),
+            Self::Unknown(capability) => write!(f, "{}", capability),
+        }
+    }
+}
+
+/// First messages of every connection, always in text. Client starts with a hello, the server either answers
+/// with its own hello listing the capabilities it supports or rejects the client and closes the connection
+#[derive(PartialEq, Debug, Clone)]
+pub enum Handshake {
+    Hello {
+        version: u16,
+        capabilities: Vec<Capability>,
+    },
+    Reject(String),
+}
+
+impl Handshake {
+    /// Hello for the version implemented by this crate
+    pub fn hello(capabilities: Vec<Capability>) -> Self {
+        Self::Hello {
+            version: VERSION,
+            capabilities,
+        }
+    }
+
+    /// Checks the peer's hello, returns the reason for rejecting it if it can't be talked to
+    pub fn accept(&self) -> Result<(), String> {
+        match self {
+            Self::Hello { version, .. } if *version < MINIMUM_VERSION || *version > VERSION => Err(format!(
+                "Unsupported protocol version {}, supported versions are {} to {}",
+                version, MINIMUM_VERSION, VERSION
+            )),
+            Self::Hello { .. } => Ok(()),
+            Self::Reject(reason) => Err(reason.clone()),
+        }
+    }
+
+    pub fn supports(&self, capability: &Capability) -> bool {
+        match self {
+            Self::Hello { capabilities, .. } => capabilities.contains(capability),
+            Self::Reject(_) => false,
+        }
+    }
+}
+
+impl FromStr for Handshake {
+    type Err = InvalidMessageError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let mut split = s.split_whitespace();
+        match split.next().map(|keyword| keyword.to_lowercase()) {
+            Some(keyword) if keyword == "hello" => {
+                let version = match split.next().map(|version| version.parse::<u16>()) {
+                    Some(Ok(version)) => version,
+                    Some(Err(_)) => return Err(InvalidMessageError { error: Error::MalformedMessage, message: String::from("Unable to parse protocol version") }),
+                    None => return Err(InvalidMessageError { error: Error::MalformedMessage, message: String::from("Protocol version missing") }),
+                };
+
+                let capabilities = split.map(|capability| capability.parse()).collect::<Result<Vec<Capability>, _>>()?;
+
+                Ok(Self::Hello { version, capabilities })
+            },
+            Some(keyword) if keyword == "reject" => Ok(Self::Reject(split.collect::<Vec<&str>>().join(" "))),
+            _ => Err(InvalidMessageError { error: Error::MalformedMessage, message: s.to_string() }),
+        }
+    }
+}
+
+impl fmt::Display for Handshake {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Hello { version, capabilities } => {
+                write!(f, "hello {}", version)?;
+                for capability in capabilities.iter() {
+                    write!(f, " {}", capability)?;
+                }
+
+                Ok(())
+            },
+            Self::Reject(reason) => write!(f, "reject {}", reason),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_hello() {
+        assert_eq!(
+            Handshake::Hello { version: 1, capabilities: vec![Capability::Json, Capability::SideBets] },
+            "hello 1 json side_bets".parse().unwrap()
+        );
+        assert_eq!(
+            Handshake::Hello { version: 1, capabilities: vec![Capability::Binary, Capability::Json] },
+            "hello 1 binary json".parse().unwrap()
+        );
+        assert_eq!(Handshake::Hello { version: 3, capabilities: vec![] }, "HELLO 3".parse().unwrap());
+        assert_eq!(
+            Handshake::Hello { version: 2, capabilities: vec![Capability::Unknown("teleport".to_string())] },
+            "hello 2 teleport".parse().unwrap()
+        );
+        assert_eq!(Handshake::Reject("Too old".to_string()), "reject Too old".parse().unwrap());
+    }
+
+    #[test]
+    fn parse_invalid() {
+        assert!("hello".parse::<Handshake>().is_err());
+        assert!("hello one json".parse::<Handshake>().is_err());
+        assert!("login bob".parse::<Handshake>().is_err());
+        assert!("".parse::<Handshake>().is_err());
+    }
+
+    #[test]
+    fn display_roundtrip() {
+        let hello = Handshake::hello(vec![Capability::Json, Capability::Variants]);
+        assert_eq!(format!("hello {} json variants", VERSION), hello.to_string());
+        assert_eq!(hello, hello.to_string().parse().unwrap());
+    }
+
+    #[test]
+    fn version_compatibility() {
+        assert!(Handshake::hello(vec![]).accept().is_ok());
+        assert!(Handshake::Hello { version: MINIMUM_VERSION - 1, capabilities: vec![] }.accept().is_err());
+        assert!(Handshake::Hello { version: VERSION + 1, capabilities: vec![] }.accept().is_err());
+        assert!(Handshake::Reject("No".to_string()).accept().is_err());
+    }
+
+    #[test]
+    fn supported_capabilities() {
+        let hello = Handshake::hello(vec![Capability::Json]);
+        assert!(hello.supports(&Capability::Json));
+        assert!(!hello.supports(&Capability::SideBets));
+    }
+}
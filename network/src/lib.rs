@@ -1,22 +1,46 @@
 #[macro_use]
 extern crate log;
 
+pub mod alias;
+pub mod catalog;
+pub mod codec;
+pub mod command;
+pub mod envelope;
+pub mod error;
+pub mod handshake;
 pub mod read;
+pub mod server_message;
+pub mod tls;
+
+pub use envelope::{Request, Response};
+pub use server_message::ServerMessage;
 
 use std::{
-    error::Error,
     fmt,
-    io::{self, prelude::*, Write},
-    net::TcpStream,
+    io::{self, Write},
     str::FromStr,
 };
 
+use catalog::Language;
+use error::Error;
+use codec::{BinaryCodec, Codec, TextCodec};
+use command::Command;
+use serde::{Deserialize, Serialize};
+
 #[derive(Debug)]
 pub struct InvalidMessageError {
+    error: Error,
     message: String,
 }
 
-impl Error for InvalidMessageError {}
+impl InvalidMessageError {
+    /// What was wrong with the message, for telling the client
+    pub fn error(&self) -> Error {
+        self.error
+    }
+}
+
+impl std::error::Error for InvalidMessageError {}
 
 impl fmt::Display for InvalidMessageError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
@@ -26,15 +50,129 @@ impl fmt::Display for InvalidMessageError {
 
 pub type MessageResult = Result<Message, InvalidMessageError>;
 
-#[derive(PartialEq, Debug)]
+/// Format the messages are exchanged in, chosen separately for every connection
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum Protocol {
+    /// Commands typed by hand and human readable responses
+    Text,
+    /// JSON objects for bots and graphical clients
+    Json,
+    /// MessagePack in binary frames, for bots and simulations sending a lot of messages
+    Binary,
+}
+
+impl Protocol {
+    /// Framing the messages of the protocol are sent with
+    pub fn codec(self) -> &'static dyn Codec {
+        match self {
+            Self::Text | Self::Json => &TextCodec,
+            Self::Binary => &BinaryCodec,
+        }
+    }
+}
+
+impl FromStr for Protocol {
+    type Err = InvalidMessageError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_ref() {
+            "text" => Ok(Self::Text),
+            "json" => Ok(Self::Json),
+            "binary" => Ok(Self::Binary),
+            _ => Err(InvalidMessageError { error: Error::UnknownProtocol, message: format!("Unknown protocol {}", s) }),
+        }
+    }
+}
+
+impl fmt::Display for Protocol {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let text = match self {
+            Self::Text => "text",
+            Self::Json => "json",
+            Self::Binary => "binary",
+        };
+
+        write!(f, "{}", text)
+    }
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+#[serde(tag = "command", content = "value", rename_all = "lowercase")]
 pub enum Message {
     Exit,
-    Login(String),
+    /// Creates an account with the password and logs in to it
+    Register { name: String, password: String },
+    Login { name: String, password: String },
+    /// Changes the password of the account that is logged in to
+    Passwd { current: String, new: String },
+    /// Continues the session of the token from an earlier connection
+    Resume(String),
     Balance,
     Deposit(u32),
     Start(u32),
+    /// Starts a round with the bet of the last one
+    Rebet,
     Hit,
-    Stand
+    Stand,
+    /// Doubles the bet, deals one more card and stands
+    Double,
+    /// Gives up the hand for half of the bet
+    Surrender,
+    Count,
+    Odds,
+    /// Keeps the connection from being closed as idle
+    Ping,
+    Protocol(Protocol),
+    /// Language of the text the server sends, e.g. "lang et"
+    Lang(Language),
+    /// Commands that can be used right now, or how to use the given one
+    Help(Option<Command>),
+}
+
+impl Message {
+    pub fn from_json(s: &str) -> MessageResult {
+        serde_json::from_str(s).map_err(|e| InvalidMessageError { error: Error::MalformedMessage, message: e.to_string() })
+    }
+
+    pub fn to_json(&self) -> String {
+        // Serializing plain enums to a string can't fail
+        serde_json::to_string(self).unwrap()
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> MessageResult {
+        rmp_serde::from_slice(bytes).map_err(|e| InvalidMessageError { error: Error::MalformedMessage, message: e.to_string() })
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        // Serializing plain enums to a vector can't fail
+        rmp_serde::to_vec_named(self).unwrap()
+    }
+
+    /// Parses the message in the given protocol's format
+    pub fn parse_as(s: &str, protocol: Protocol) -> MessageResult {
+        match protocol {
+            Protocol::Text => s.parse(),
+            Protocol::Json => Self::from_json(s),
+            Protocol::Binary => Err(InvalidMessageError { error: Error::MalformedMessage, message: String::from("Binary message sent as text") }),
+        }
+    }
+
+    /// Decodes the bytes of a frame in the given protocol's format
+    pub fn decode(bytes: &[u8], protocol: Protocol) -> MessageResult {
+        match protocol {
+            Protocol::Binary => Self::from_bytes(bytes),
+            _ => Self::parse_as(&String::from_utf8_lossy(bytes), protocol),
+        }
+    }
+
+    pub fn encode(&self, protocol: Protocol) -> Vec<u8> {
+        match protocol {
+            Protocol::Text => self.to_string().into_bytes(),
+            Protocol::Json => self.to_json().into_bytes(),
+            Protocol::Binary => self.to_bytes(),
+        }
+    }
 }
 
 impl FromStr for Message {
@@ -46,26 +184,52 @@ impl FromStr for Message {
         match split.next() {
             Some(keyword) => match keyword.to_lowercase().as_ref() {
                 "exit" => Ok(Self::Exit),
-                "login" => Ok(Self::Login(split.collect::<Vec<&str>>().join(" "))),
+                "register" | "login" => {
+                    let name = split.next();
+                    let password = match name {
+                        // Rest of the line is the password, unless it's quoted
+                        Some(name) => {
+                            let rest = s[keyword.len()..].trim_start();
+                            password(rest[name.len()..].trim())?
+                        },
+                        None => String::new(),
+                    };
+                    match (name, password.is_empty()) {
+                        (Some(name), false) if keyword.eq_ignore_ascii_case("register") => Ok(Self::Register { name: name.to_string(), password }),
+                        (Some(name), false) => Ok(Self::Login { name: name.to_string(), password }),
+                        (Some(_), true) => Err(InvalidMessageError { error: Error::MissingArgument, message: String::from("Password missing") }),
+                        (None, _) => Err(InvalidMessageError { error: Error::MissingArgument, message: String::from("Name missing") }),
+                    }
+                },
+                "resume" => match split.next() {
+                    Some(token) => Ok(Self::Resume(token.to_string())),
+                    None => Err(InvalidMessageError { error: Error::MissingArgument, message: String::from("Token missing") })
+                },
+                // Two passwords can't be told apart by spaces alone, the ones with spaces are quoted
+                "passwd" => match arguments(&s[keyword.len()..])?.as_slice() {
+                    [current, new] => Ok(Self::Passwd { current: current.to_string(), new: new.to_string() }),
+                    [_, _, ..] => Err(InvalidMessageError { error: Error::MalformedMessage, message: String::from("Passwords with spaces have to be quoted") }),
+                    _ => Err(InvalidMessageError { error: Error::MissingArgument, message: String::from("Passwords missing") }),
+                },
                 "balance" => Ok(Self::Balance),
                 "deposit" => {
                     match split.next() {
                         Some(amount) => {
                             match amount.to_string().parse::<u32>() {
                                 Ok(amount) => {
-                                    if amount <= 0 {
-                                        return Err(InvalidMessageError { message: String::from("Deposit amount has to be a positive number") });
+                                    if amount == 0 {
+                                        return Err(InvalidMessageError { error: Error::NonPositiveAmount, message: String::from("Deposit amount has to be a positive number") });
                                     }
 
                                     Ok(Self::Deposit(amount))
                                 },
                                 Err(e) => {
                                     warn!("{}", e);
-                                    Err(InvalidMessageError { message: String::from("Unable to parse input to integer") })
+                                    Err(InvalidMessageError { error: Error::InvalidNumber, message: String::from("Unable to parse input to integer") })
                                 }
                             }
                         },
-                        None => Err(InvalidMessageError { message: String::from("Amount missing") })
+                        None => Err(InvalidMessageError { error: Error::MissingArgument, message: String::from("Amount missing") })
                     }
                 },
                 "start" => {
@@ -73,53 +237,140 @@ impl FromStr for Message {
                         Some(amount) => {
                             match amount.to_string().parse::<u32>() {
                                 Ok(amount) => {
-                                    if amount <= 0 {
-                                        return Err(InvalidMessageError { message: String::from("Bet amount has to be a positive number") });
+                                    if amount == 0 {
+                                        return Err(InvalidMessageError { error: Error::NonPositiveAmount, message: String::from("Bet amount has to be a positive number") });
                                     }
 
                                     Ok(Self::Start(amount))
                                 },
                                 Err(e) => {
                                     warn!("{}", e);
-                                    Err(InvalidMessageError { message: String::from("Unable to parse input to integer") })
+                                    Err(InvalidMessageError { error: Error::InvalidNumber, message: String::from("Unable to parse input to integer") })
                                 }
                             }
                         },
-                        None => Err(InvalidMessageError { message: String::from("Amount missing") })
+                        None => Err(InvalidMessageError { error: Error::MissingArgument, message: String::from("Amount missing") })
                     }
                 },
+                "rebet" => Ok(Self::Rebet),
                 "hit" => Ok(Self::Hit),
                 "stand" => Ok(Self::Stand),
-                _ => Err(InvalidMessageError { message: s.to_string() }),
+                "double" => Ok(Self::Double),
+                "surrender" => Ok(Self::Surrender),
+                "count" => Ok(Self::Count),
+                "odds" => Ok(Self::Odds),
+                "ping" => Ok(Self::Ping),
+                "protocol" => match split.next() {
+                    Some(protocol) => Ok(Self::Protocol(protocol.parse()?)),
+                    None => Err(InvalidMessageError { error: Error::MissingArgument, message: String::from("Protocol missing") })
+                },
+                "help" => match split.next() {
+                    Some(command) => Ok(Self::Help(Some(command.parse()?))),
+                    None => Ok(Self::Help(None)),
+                },
+                "lang" => match split.next() {
+                    Some(language) => Ok(Self::Lang(language.parse()?)),
+                    None => Err(InvalidMessageError { error: Error::MissingArgument, message: String::from("Language missing") })
+                },
+                _ => Err(InvalidMessageError { error: Error::UnknownCommand, message: s.to_string() }),
             },
-            None => Err(InvalidMessageError { message: String::from("Keyword missing") })
+            None => Err(InvalidMessageError { error: Error::UnknownCommand, message: String::from("Keyword missing") })
+        }
+    }
+}
+
+/// Words of the text, a word in double quotes can have spaces in it and `\"` or `\\` for a quote or a backslash
+fn arguments(s: &str) -> Result<Vec<String>, InvalidMessageError> {
+    let mut arguments = Vec::new();
+    let mut chars = s.trim().chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if c.is_whitespace() {
+            continue;
         }
+
+        let mut argument = String::new();
+        if c == '"' {
+            loop {
+                match chars.next() {
+                    Some('"') => break,
+                    Some('\\') => argument.extend(chars.next()),
+                    Some(c) => argument.push(c),
+                    None => return Err(InvalidMessageError { error: Error::MalformedMessage, message: String::from("Closing quote missing") }),
+                }
+            }
+        } else {
+            argument.push(c);
+            while let Some(c) = chars.next_if(|c| !c.is_whitespace()) {
+                argument.push(c);
+            }
+        }
+
+        arguments.push(argument);
+    }
+
+    Ok(arguments)
+}
+
+/// Password at the end of a line, one in quotes can start or end with spaces and nothing can follow it
+fn password(s: &str) -> Result<String, InvalidMessageError> {
+    if !s.starts_with('"') {
+        return Ok(s.to_string());
+    }
+
+    match arguments(s)?.as_slice() {
+        [password] => Ok(password.to_string()),
+        _ => Err(InvalidMessageError { error: Error::MalformedMessage, message: String::from("Nothing can follow the quoted password") }),
     }
 }
 
+/// Argument the way `arguments` reads it back, quoted only when it has to be
+fn quote(argument: &str) -> String {
+    if !argument.is_empty() && !argument.starts_with('"') && !argument.contains(char::is_whitespace) {
+        return argument.to_string();
+    }
+
+    format!("\"{}\"", argument.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
 impl fmt::Display for Message {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         let text = match self {
             Self::Exit => String::from("exit"),
-            Self::Login(username) => format!("login {}", username),
+            Self::Register { name, password } => format!("register {} {}", name, quote(password)),
+            Self::Login { name, password } => format!("login {} {}", name, quote(password)),
+            Self::Passwd { current, new } => format!("passwd {} {}", quote(current), quote(new)),
+            Self::Resume(token) => format!("resume {}", token),
             Self::Balance => String::from("balance"),
             Self::Deposit(amount) => format!("deposit {}", amount),
             Self::Start(amount) => format!("start {}", amount),
+            Self::Rebet => String::from("rebet"),
             Self::Hit => String::from("hit"),
-            Self::Stand => String::from("stand")
+            Self::Stand => String::from("stand"),
+            Self::Double => String::from("double"),
+            Self::Surrender => String::from("surrender"),
+            Self::Count => String::from("count"),
+            Self::Odds => String::from("odds"),
+            Self::Ping => String::from("ping"),
+            Self::Protocol(protocol) => format!("protocol {}", protocol),
+            Self::Lang(language) => format!("lang {}", language),
+            Self::Help(Some(command)) => format!("help {}", command),
+            Self::Help(None) => String::from("help"),
         };
 
         write!(f, "{}", text)
     }
 }
 
-pub fn send(mut stream: &TcpStream, message: impl fmt::Display) -> Result<(), io::Error> {
-    let string = message.to_string();
-    let bytes = string.as_bytes().len();
-    debug!("Writing {} bytes to stream", bytes);
-    stream.write_all(format!("{}\r\n{}", bytes, string).as_bytes())?;
+/// Sends the message as text to anything that can be written to, `&TcpStream`, `&mut UnixStream`, `Vec<u8>` etc.
+pub fn send(writer: impl Write, message: impl fmt::Display) -> Result<(), io::Error> {
+    send_frame(writer, &TextCodec, message.to_string().as_bytes())
+}
 
-    Ok(())
+/// Sends the bytes as a single frame using the codec
+pub fn send_frame(mut writer: impl Write, codec: &dyn Codec, frame: &[u8]) -> Result<(), io::Error> {
+    codec.write_frame(&mut writer, frame)?;
+    writer.flush()
 }
 
 #[cfg(test)]
@@ -127,7 +378,7 @@ mod tests {
     use super::*;
 
     mod message {
-        use super::Message;
+        use super::{Command, Error, Language, Message, Protocol};
         #[test]
         fn valid_parsing() {
             assert_eq!(Message::Exit, "exit".parse().unwrap());
@@ -139,12 +390,150 @@ mod tests {
         #[test]
         fn parse_all_types() {
             assert_eq!(Message::Exit, "exit".parse().unwrap());
-            assert_eq!(Message::Login("fooBAR".to_string()), "login fooBAR".parse().unwrap());
+            assert_eq!(Message::Login { name: "fooBAR".to_string(), password: "hunter 2".to_string() }, "login fooBAR hunter 2".parse().unwrap());
+            assert_eq!(Message::Register { name: "bob".to_string(), password: "secret".to_string() }, "REGISTER bob secret".parse().unwrap());
+            assert_eq!(Message::Passwd { current: "old".to_string(), new: "new".to_string() }, "passwd old new".parse().unwrap());
+            assert_eq!(Message::Passwd { current: "old one".to_string(), new: "new".to_string() }, r#"passwd "old one" new"#.parse().unwrap());
+            assert_eq!(Message::Resume("0a1b".to_string()), "resume 0a1b".parse().unwrap());
             assert_eq!(Message::Balance, "balance".parse().unwrap());
             assert_eq!(Message::Deposit(50), "deposit 50".parse().unwrap());
             assert_eq!(Message::Start(25), "start 25".parse().unwrap());
+            assert_eq!(Message::Rebet, "rebet".parse().unwrap());
             assert_eq!(Message::Hit, "hit".parse().unwrap());
             assert_eq!(Message::Stand, "stand".parse().unwrap());
+            assert_eq!(Message::Double, "double".parse().unwrap());
+            assert_eq!(Message::Surrender, "surrender".parse().unwrap());
+            assert_eq!(Message::Count, "count".parse().unwrap());
+            assert_eq!(Message::Odds, "odds".parse().unwrap());
+            assert_eq!(Message::Ping, "ping".parse().unwrap());
+            assert_eq!(Message::Protocol(Protocol::Json), "protocol json".parse().unwrap());
+            assert_eq!(Message::Lang(Language::Estonian), "lang et".parse().unwrap());
+            assert_eq!(Message::Help(None), "help".parse().unwrap());
+            assert_eq!(Message::Help(Some(Command::Stand)), "help stand".parse().unwrap());
+        }
+
+        #[test]
+        fn parse_errors() {
+            let error = |s: &str| s.parse::<Message>().unwrap_err().error();
+
+            assert_eq!(Error::UnknownCommand, error("fly"));
+            assert_eq!(Error::UnknownCommand, error(""));
+            assert_eq!(Error::MissingArgument, error("deposit"));
+            assert_eq!(Error::MissingArgument, error("login bob"));
+            assert_eq!(Error::MissingArgument, error("passwd old"));
+            assert_eq!(Error::MalformedMessage, error("passwd old new password"));
+            assert_eq!(Error::MalformedMessage, error(r#"login bob "secret" more"#));
+            assert_eq!(Error::MalformedMessage, error(r#"login bob "secret"#));
+            assert_eq!(Error::MalformedMessage, error(r#"passwd old "new password"#));
+            assert_eq!(Error::InvalidNumber, error("start ten"));
+            assert_eq!(Error::NonPositiveAmount, error("start 0"));
+            assert_eq!(Error::UnknownProtocol, error("protocol xml"));
+            assert_eq!(Error::UnknownLanguage, error("lang fi"));
+            assert_eq!(Error::UnknownCommand, error("help fly"));
+        }
+
+        #[test]
+        fn json_roundtrip() {
+            let messages = [
+                Message::Exit,
+                Message::Login { name: "fooBAR".to_string(), password: "secret".to_string() },
+                Message::Deposit(50),
+                Message::Hit,
+                Message::Protocol(Protocol::Text),
+            ];
+
+            for message in messages.iter() {
+                assert_eq!(*message, Message::from_json(&message.to_json()).unwrap());
+            }
+        }
+
+        #[test]
+        fn text_roundtrip() {
+            let messages = [
+                Message::Login { name: "fooBAR".to_string(), password: "hunter 2".to_string() },
+                Message::Login { name: "bob".to_string(), password: " spaces around ".to_string() },
+                Message::Register { name: "bob".to_string(), password: r#""quoted" \ password"#.to_string() },
+                Message::Register { name: "bob".to_string(), password: "secret".to_string() },
+                Message::Passwd { current: "old".to_string(), new: "new".to_string() },
+                Message::Passwd { current: "old password".to_string(), new: " new  password ".to_string() },
+                Message::Passwd { current: r#""quoted""#.to_string(), new: r#"back\slash "#.to_string() },
+            ];
+
+            for message in messages.iter() {
+                assert_eq!(*message, message.to_string().parse().unwrap());
+            }
+        }
+
+        #[test]
+        fn json_parsing() {
+            assert_eq!(Message::Start(25), Message::from_json(r#"{"command":"start","value":25}"#).unwrap());
+            assert_eq!(Message::Stand, Message::from_json(r#"{"command":"stand"}"#).unwrap());
+            assert_eq!(Message::Help(None), Message::from_json(r#"{"command":"help"}"#).unwrap());
+            assert_eq!(Message::Help(Some(Command::Odds)), Message::from_json(r#"{"command":"help","value":"odds"}"#).unwrap());
+            assert!(Message::from_json(r#"{"command":"fly"}"#).is_err());
+            assert!(Message::from_json("stand").is_err());
+        }
+
+        #[test]
+        fn parse_as_protocol() {
+            assert_eq!(Message::Hit, Message::parse_as("hit", Protocol::Text).unwrap());
+            assert_eq!(Message::Hit, Message::parse_as(r#"{"command":"hit"}"#, Protocol::Json).unwrap());
+            assert!(Message::parse_as("hit", Protocol::Json).is_err());
+        }
+
+        #[test]
+        fn encode_decode() {
+            let messages = [
+                Message::Exit,
+                Message::Login { name: "fooBAR".to_string(), password: "secret".to_string() },
+                Message::Start(25),
+                Message::Stand,
+                Message::Protocol(Protocol::Binary),
+                Message::Lang(Language::English),
+                Message::Help(None),
+                Message::Help(Some(Command::Hit)),
+            ];
+
+            for protocol in [Protocol::Text, Protocol::Json, Protocol::Binary].iter() {
+                for message in messages.iter() {
+                    assert_eq!(*message, Message::decode(&message.encode(*protocol), *protocol).unwrap());
+                }
+            }
+
+            // Binary encoding is smaller than JSON
+            assert!(Message::Start(25).to_bytes().len() < Message::Start(25).to_json().len());
+            assert!(Message::from_bytes(b"hit").is_err());
+        }
+    }
+
+    mod transport {
+        use super::*;
+        use crate::read;
+
+        #[test]
+        fn in_memory() {
+            let mut pipe = Vec::new();
+            send(&mut pipe, Message::Hit).unwrap();
+            send_frame(&mut pipe, Protocol::Binary.codec(), &Message::Start(5).encode(Protocol::Binary)).unwrap();
+
+            let mut reader = io::Cursor::new(pipe);
+            assert_eq!("hit", read::read(&mut reader).unwrap());
+            let frame = read::read_frame(&mut reader, Protocol::Binary.codec(), read::DEFAULT_MAX_FRAME_SIZE).unwrap();
+            assert_eq!(Message::Start(5), Message::decode(&frame, Protocol::Binary).unwrap());
+        }
+
+        #[cfg(unix)]
+        #[test]
+        fn unix_socket() {
+            use std::os::unix::net::UnixStream;
+
+            let (client, server) = UnixStream::pair().unwrap();
+            send(&client, Message::Login { name: "bob".to_string(), password: "secret".to_string() }).unwrap();
+            drop(client);
+
+            let mut reader = io::BufReader::new(server);
+            assert_eq!("login bob secret", read::read(&mut reader).unwrap());
+            assert_eq!(read::ErrorKind::ConnectionLost, read::read(&mut reader).unwrap_err().kind());
         }
     }
 }
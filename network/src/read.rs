@@ -1,7 +1,8 @@
+use crate::codec::{Codec, TextCodec};
 use std::{
     convert::From,
     error, fmt,
-    io::{self, prelude::*},
+    io,
     num::ParseIntError,
 };
 
@@ -10,6 +11,12 @@ pub enum ErrorKind {
     ConnectionLost,
     InvalidBytesRead,
     IOError,
+    /// Length prefix is bigger than the allowed maximum
+    FrameTooLarge,
+    /// Stream ended before the whole frame was read
+    TruncatedFrame,
+    /// Nothing arrived before the stream's read timeout
+    TimedOut,
 }
 
 #[derive(Debug)]
@@ -30,6 +37,9 @@ impl fmt::Display for ErrorKind {
             Self::ConnectionLost => "connection with the server is lost",
             Self::InvalidBytesRead => "stream returned invalid string for message bytes length",
             Self::IOError => "IO Error",
+            Self::FrameTooLarge => "message is bigger than the allowed maximum",
+            Self::TruncatedFrame => "stream ended in the middle of a message",
+            Self::TimedOut => "no message arrived in time",
         };
 
         write!(f, "{}", reason)
@@ -86,9 +96,15 @@ impl From<ParseIntError> for Error {
 
 impl From<io::Error> for Error {
     fn from(error: io::Error) -> Error {
+        // Read timeouts show up as either of these depending on the platform
+        let kind = match error.kind() {
+            io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut => ErrorKind::TimedOut,
+            _ => ErrorKind::IOError,
+        };
+
         Error {
             repr: Repr::Extended(ExtendedError {
-                kind: ErrorKind::IOError,
+                kind,
                 error: Box::new(error),
             }),
         }
@@ -97,24 +113,137 @@ impl From<io::Error> for Error {
 
 pub type ReadResult = Result<String, Error>;
 
+/// Largest message `read` accepts
+pub const DEFAULT_MAX_FRAME_SIZE: u64 = 64 * 1024;
+
 pub fn read(reader: &mut impl io::BufRead) -> ReadResult {
-    let mut message = String::new();
+    read_limited(reader, DEFAULT_MAX_FRAME_SIZE)
+}
+
+/// Reads a text framed message that is at most `max_size` bytes long
+pub fn read_limited(reader: &mut impl io::BufRead, max_size: u64) -> ReadResult {
+    let frame = read_frame(reader, &TextCodec, max_size)?;
+
+    Ok(String::from_utf8_lossy(&frame).to_string())
+}
 
-    debug!("Waiting for incoming message");
-    match reader.read_line(&mut message) {
-        Ok(0) => return Err(Error::from(ErrorKind::ConnectionLost)),
-        Ok(_) => (),
-        Err(e) => return Err(Error::from(e)),
+/// Reads the bytes of a single frame split from the stream by the codec
+pub fn read_frame(reader: &mut impl io::BufRead, codec: &dyn Codec, max_size: u64) -> Result<Vec<u8>, Error> {
+    codec.read_frame(reader, max_size)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    fn frame(message: &[u8]) -> Vec<u8> {
+        let mut frame = format!("{}\r\n", message.len()).into_bytes();
+        frame.extend_from_slice(message);
+        frame
     }
 
-    debug!("Received {:?} from server", message);
-    let size = message.trim_end().to_string().parse::<u64>()?;
+    #[test]
+    fn reads_consecutive_frames() {
+        let mut bytes = frame(b"login bob");
+        bytes.extend(frame(b""));
+        bytes.extend(frame(b"hit"));
+        let mut reader = io::Cursor::new(bytes);
+
+        assert_eq!("login bob", read(&mut reader).unwrap());
+        assert_eq!("", read(&mut reader).unwrap());
+        assert_eq!("hit", read(&mut reader).unwrap());
+        assert_eq!(ErrorKind::ConnectionLost, read(&mut reader).unwrap_err().kind());
+    }
+
+    #[test]
+    fn rejects_oversize_frames() {
+        let mut reader = io::Cursor::new(b"18446744073709551615\r\n".to_vec());
+        assert_eq!(ErrorKind::FrameTooLarge, read(&mut reader).unwrap_err().kind());
 
-    debug!("Message is {} bytes long", size);
+        let mut reader = io::Cursor::new(frame(b"0123456789"));
+        assert_eq!(ErrorKind::FrameTooLarge, read_limited(&mut reader, 9).unwrap_err().kind());
 
-    let mut buffer = Vec::new();
-    reader.take(size).read_to_end(&mut buffer)?;
-    let message = String::from_utf8_lossy(&buffer).to_string();
+        let mut reader = io::Cursor::new(frame(b"0123456789"));
+        assert_eq!("0123456789", read_limited(&mut reader, 10).unwrap());
+    }
 
-    Ok(message)
+    #[test]
+    fn rejects_truncated_frames() {
+        let mut reader = io::Cursor::new(b"10\r\nhit".to_vec());
+        assert_eq!(ErrorKind::TruncatedFrame, read(&mut reader).unwrap_err().kind());
+
+        let mut reader = io::Cursor::new(b"10".to_vec());
+        assert_eq!(ErrorKind::TruncatedFrame, read(&mut reader).unwrap_err().kind());
+    }
+
+    #[test]
+    fn rejects_invalid_length_prefix() {
+        let mut reader = io::Cursor::new(b"ten\r\n0123456789".to_vec());
+        assert_eq!(ErrorKind::InvalidBytesRead, read(&mut reader).unwrap_err().kind());
+
+        let mut reader = io::Cursor::new(b"-1\r\n".to_vec());
+        assert_eq!(ErrorKind::InvalidBytesRead, read(&mut reader).unwrap_err().kind());
+
+        // Never ending length line doesn't get buffered
+        let mut reader = io::Cursor::new(vec![b'1'; 1024 * 1024]);
+        assert_eq!(ErrorKind::InvalidBytesRead, read(&mut reader).unwrap_err().kind());
+    }
+
+    #[test]
+    fn fuzz_random_bytes() {
+        let mut rng = StdRng::seed_from_u64(2024);
+
+        for _ in 0..10_000 {
+            let length = rng.gen_range(0, 64);
+            let mut bytes: Vec<u8> = (0..length).map(|_| rng.gen()).collect();
+            // Mostly digits and line breaks so that some of the inputs get past the length prefix
+            for byte in bytes.iter_mut() {
+                if rng.gen_bool(0.7) {
+                    *byte = b"0123456789\r\n"[rng.gen_range(0, 12)];
+                }
+            }
+
+            let mut reader = io::Cursor::new(bytes.clone());
+            while let Ok(message) = read_limited(&mut reader, 32) {
+                assert!(message.len() <= 32 * 3, "{:?}", bytes);
+            }
+        }
+    }
+
+    #[test]
+    fn fuzz_roundtrip() {
+        let mut rng = StdRng::seed_from_u64(2024);
+
+        for _ in 0..1_000 {
+            let messages: Vec<String> = (0..rng.gen_range(1, 5))
+                .map(|_| {
+                    let length = rng.gen_range(0, 200);
+                    (0..length).map(|_| rng.gen_range(b' ', b'~') as char).collect()
+                })
+                .collect();
+
+            let mut bytes = Vec::new();
+            for message in messages.iter() {
+                bytes.extend(frame(message.as_bytes()));
+            }
+
+            // Cutting the stream short anywhere must give a truncated frame or connection lost, never garbage
+            let cut = rng.gen_range(0, bytes.len() + 1);
+            let mut reader = io::Cursor::new(bytes[..cut].to_vec());
+            for message in messages.iter() {
+                match read(&mut reader) {
+                    Ok(read) => assert_eq!(*message, read),
+                    Err(e) => {
+                        assert!(
+                            e.kind() == ErrorKind::TruncatedFrame || e.kind() == ErrorKind::ConnectionLost,
+                            "{}",
+                            e
+                        );
+                        break;
+                    }
+                }
+            }
+        }
+    }
 }
@@ -0,0 +1,269 @@
+use crate::command::Command;
+use crate::error::Error;
+use crate::catalog::{self, Language};
+use crate::{InvalidMessageError, Protocol};
+use serde::{Deserialize, Serialize};
+use std::{fmt, str::FromStr};
+
+/// Cards in compact notation ("AH", "TS") and the total they add up to
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct Hand {
+    pub cards: Vec<String>,
+    pub total: u8,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum Outcome {
+    PlayerWon,
+    DealerWon,
+    Draw,
+    Bust,
+    /// Player gave up the hand and got half of the bet back
+    Surrendered,
+}
+
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", catalog::outcome(*self, Language::English))
+    }
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct SystemCount {
+    pub system: String,
+    pub running: i32,
+    pub true_count: f64,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct TotalProbability {
+    pub total: u8,
+    pub probability: f64,
+}
+
+/// Everything a player can do in a round, which of them are legal depends on the hand and the table's rules
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum Action {
+    Hit,
+    Stand,
+    Double,
+    Split,
+    Surrender,
+    Insurance,
+}
+
+/// Name of the action, the same as the command that takes it
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let text = match self {
+            Self::Hit => "hit",
+            Self::Stand => "stand",
+            Self::Double => "double",
+            Self::Split => "split",
+            Self::Surrender => "surrender",
+            Self::Insurance => "insurance",
+        };
+
+        write!(f, "{}", text)
+    }
+}
+
+/// Action the player can take right now and what it takes from the balance
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone, Copy)]
+pub struct LegalAction {
+    pub action: Action,
+    pub cost: u32,
+}
+
+/// How a command is used, `available` tells if it can be used in the connection's current state
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct CommandHelp {
+    pub command: Command,
+    pub usage: String,
+    pub description: String,
+    pub available: bool,
+}
+
+/// Everything the server can send to a client
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ServerMessage {
+    Welcome,
+    LoggedIn { balance: u32 },
+    /// Token for resuming the session from another connection, valid until `expires_in` seconds after disconnecting
+    Session { token: String, expires_in: u64 },
+    /// Answer to `Message::Passwd`
+    PasswordChanged,
+    Balance { balance: u32 },
+    Deposited { amount: u32, balance: u32 },
+    /// Round is ongoing and waiting for the player to hit or stand, the dealer's hand only has the upcard
+    HandState { dealer: Hand, player: Hand },
+    RoundResult { outcome: Outcome, dealer: Hand, player: Hand, balance: u32 },
+    Count { counts: Vec<SystemCount> },
+    Odds { upcard: String, totals: Vec<TotalProbability>, bust: f64 },
+    ProtocolChanged { protocol: Protocol },
+    LanguageChanged { language: Language },
+    /// Answer to `Message::Help`
+    Help { commands: Vec<CommandHelp> },
+    /// Answer to `Message::Ping`
+    Pong,
+    /// Shoe ran low and was refilled with freshly shuffled cards, card counts start over
+    Shuffled,
+    /// Code of an `error::Error` and its text in the language of the connection
+    Error { code: u16, text: String },
+}
+
+impl ServerMessage {
+    /// Error with its code and text in the language, see `error::Error`
+    pub fn error(error: Error, language: Language) -> Self {
+        Self::Error {
+            code: error.code(),
+            text: error.text(language),
+        }
+    }
+
+    /// Human readable form of the message in the language, see `catalog`
+    pub fn text(&self, language: Language) -> String {
+        catalog::message(self, language)
+    }
+
+    pub fn from_json(s: &str) -> Result<Self, InvalidMessageError> {
+        serde_json::from_str(s).map_err(|e| InvalidMessageError { error: Error::MalformedMessage, message: e.to_string() })
+    }
+
+    pub fn to_json(&self) -> String {
+        // Serializing plain enums and structs to a string can't fail
+        serde_json::to_string(self).unwrap()
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, InvalidMessageError> {
+        rmp_serde::from_slice(bytes).map_err(|e| InvalidMessageError { error: Error::MalformedMessage, message: e.to_string() })
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        // Serializing plain enums and structs to a vector can't fail
+        rmp_serde::to_vec_named(self).unwrap()
+    }
+
+    pub fn encode(&self, protocol: Protocol) -> Vec<u8> {
+        match protocol {
+            Protocol::Text => self.to_string().into_bytes(),
+            Protocol::Json => self.to_json().into_bytes(),
+            Protocol::Binary => self.to_bytes(),
+        }
+    }
+
+    /// Decodes the bytes of a frame, text protocol messages are only meant for humans and can't be decoded
+    pub fn decode(bytes: &[u8], protocol: Protocol) -> Result<Self, InvalidMessageError> {
+        match protocol {
+            Protocol::Text => Err(InvalidMessageError { error: Error::MalformedMessage, message: String::from_utf8_lossy(bytes).to_string() }),
+            Protocol::Json => Self::from_json(&String::from_utf8_lossy(bytes)),
+            Protocol::Binary => Self::from_bytes(bytes),
+        }
+    }
+}
+
+/// Human readable form of the message used by the text protocol, in English
+impl fmt::Display for ServerMessage {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.text(Language::English))
+    }
+}
+
+/// Parses the JSON form, the human readable text isn't meant to be parsed back
+impl FromStr for ServerMessage {
+    type Err = InvalidMessageError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::from_json(s)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn json_format() {
+        assert_eq!(r#"{"type":"welcome"}"#, ServerMessage::Welcome.to_json());
+        assert_eq!(r#"{"type":"logged_in","balance":50}"#, ServerMessage::LoggedIn { balance: 50 }.to_json());
+
+        let message = ServerMessage::RoundResult {
+            outcome: Outcome::DealerWon,
+            dealer: Hand { cards: vec!["TH".to_string(), "9S".to_string()], total: 19 },
+            player: Hand { cards: vec!["8D".to_string(), "9C".to_string()], total: 17 },
+            balance: 90,
+        };
+        assert_eq!(
+            r#"{"type":"round_result","outcome":"dealer_won","dealer":{"cards":["TH","9S"],"total":19},"player":{"cards":["8D","9C"],"total":17},"balance":90}"#,
+            message.to_json()
+        );
+    }
+
+    #[test]
+    fn text_format() {
+        let message = ServerMessage::HandState {
+            dealer: Hand { cards: vec!["TH".to_string(), "2S".to_string()], total: 12 },
+            player: Hand { cards: vec!["8D".to_string(), "7C".to_string()], total: 15 },
+        };
+        assert_eq!("Dealer Hand: 12\r\nPlayer Hand: 15", message.to_string());
+        assert_eq!("Not logged in", ServerMessage::error(Error::NotLoggedIn, Language::English).to_string());
+        assert_eq!(
+            ServerMessage::Error { code: 2, text: String::from("Pole sisse logitud") },
+            ServerMessage::error(Error::NotLoggedIn, Language::Estonian)
+        );
+    }
+
+    #[test]
+    fn json_roundtrip() {
+        let messages = [
+            ServerMessage::Welcome,
+            ServerMessage::LoggedIn { balance: 10 },
+            ServerMessage::HandState {
+                dealer: Hand { cards: vec!["AS".to_string(), "5H".to_string()], total: 6 },
+                player: Hand { cards: vec!["TD".to_string(), "KC".to_string()], total: 20 },
+            },
+            ServerMessage::Odds {
+                upcard: "AS".to_string(),
+                totals: vec![TotalProbability { total: 17, probability: 0.25 }],
+                bust: 0.75,
+            },
+            ServerMessage::error(Error::NoRoundInProgress, Language::English),
+        ];
+
+        for message in messages.iter() {
+            assert_eq!(*message, message.to_json().parse().unwrap());
+        }
+    }
+
+    #[test]
+    fn binary_roundtrip() {
+        let messages = [
+            ServerMessage::Welcome,
+            ServerMessage::Deposited { amount: 5, balance: 15 },
+            ServerMessage::RoundResult {
+                outcome: Outcome::Bust,
+                dealer: Hand { cards: vec!["AS".to_string(), "5H".to_string()], total: 6 },
+                player: Hand { cards: vec!["TD".to_string(), "KC".to_string(), "2H".to_string()], total: 22 },
+                balance: 0,
+            },
+            ServerMessage::Count { counts: vec![SystemCount { system: "Hi-Lo".to_string(), running: -2, true_count: -0.5 }] },
+            ServerMessage::ProtocolChanged { protocol: Protocol::Binary },
+            ServerMessage::error(Error::NoRoundInProgress, Language::English),
+        ];
+
+        for message in messages.iter() {
+            assert_eq!(*message, ServerMessage::decode(&message.encode(Protocol::Binary), Protocol::Binary).unwrap());
+            assert!(message.to_bytes().len() < message.to_json().len());
+        }
+    }
+
+    #[test]
+    fn invalid_json() {
+        assert!("Dealer Hand: 12".parse::<ServerMessage>().is_err());
+        assert!(r#"{"type":"fly"}"#.parse::<ServerMessage>().is_err());
+        assert!(r#"{"type":"error","text":"code missing"}"#.parse::<ServerMessage>().is_err());
+    }
+}
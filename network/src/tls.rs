@@ -0,0 +1,316 @@
+use ring::digest;
+use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rustls::client::WebPkiServerVerifier;
+use rustls::crypto::{self, CryptoProvider};
+use rustls::pki_types::{CertificateDer, ServerName, UnixTime};
+use rustls::{ClientConfig, ClientConnection, DigitallySignedStruct, RootCertStore, ServerConfig, ServerConnection};
+use rustls::{SignatureScheme, StreamOwned};
+use std::fs::File;
+use std::io::{self, BufReader, Read, Write};
+use std::net::{Shutdown, SocketAddr, TcpStream};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use std::{convert::TryFrom, fmt, str::FromStr};
+
+/// How long a read holds the connection before letting writes through
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Time the other end has to finish the TLS handshake when connecting
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+fn provider() -> Arc<CryptoProvider> {
+    Arc::new(crypto::ring::default_provider())
+}
+
+fn invalid_data(error: impl fmt::Display) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, error.to_string())
+}
+
+/// SHA-256 of a certificate, the way clients pin a self-signed server certificate
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub struct Fingerprint([u8; 32]);
+
+impl Fingerprint {
+    pub fn of(certificate: &[u8]) -> Self {
+        let mut fingerprint = [0; 32];
+        fingerprint.copy_from_slice(digest::digest(&digest::SHA256, certificate).as_ref());
+        Self(fingerprint)
+    }
+}
+
+/// Hex with or without colons between the bytes, in either case, as printed by `openssl x509 -fingerprint`
+impl FromStr for Fingerprint {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let digits: Vec<char> = s.chars().filter(|c| *c != ':').collect();
+        if digits.len() != 64 {
+            return Err(format!("Fingerprint has to be 32 bytes long, got \"{}\"", s));
+        }
+
+        let mut fingerprint = [0; 32];
+        for (byte, pair) in fingerprint.iter_mut().zip(digits.chunks(2)) {
+            let pair: String = pair.iter().collect();
+            *byte = u8::from_str_radix(&pair, 16).map_err(|_| format!("Fingerprint isn't hex, got \"{}\"", s))?;
+        }
+
+        Ok(Self(fingerprint))
+    }
+}
+
+impl fmt::Display for Fingerprint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let bytes: Vec<String> = self.0.iter().map(|byte| format!("{:02X}", byte)).collect();
+        write!(f, "{}", bytes.join(":"))
+    }
+}
+
+fn load_certificates(path: &Path) -> io::Result<Vec<CertificateDer<'static>>> {
+    let certificates = rustls_pemfile::certs(&mut BufReader::new(File::open(path)?)).collect::<io::Result<Vec<_>>>()?;
+    if certificates.is_empty() {
+        return Err(invalid_data(format!("No certificates in {}", path.display())));
+    }
+
+    Ok(certificates)
+}
+
+/// Fingerprint of the first certificate in the PEM file, for telling clients what to pin
+pub fn certificate_fingerprint(path: &Path) -> io::Result<Fingerprint> {
+    Ok(Fingerprint::of(&load_certificates(path)?[0]))
+}
+
+/// Server side configuration from a PEM certificate chain and private key
+pub fn server_config(certificate: &Path, key: &Path) -> io::Result<Arc<ServerConfig>> {
+    let certificates = load_certificates(certificate)?;
+    let key = rustls_pemfile::private_key(&mut BufReader::new(File::open(key)?))?
+        .ok_or_else(|| invalid_data(format!("No private key in {}", key.display())))?;
+
+    let config = ServerConfig::builder_with_provider(provider())
+        .with_safe_default_protocol_versions()
+        .map_err(invalid_data)?
+        .with_no_client_auth()
+        .with_single_cert(certificates, key)
+        .map_err(invalid_data)?;
+
+    Ok(Arc::new(config))
+}
+
+/// Client side configuration, the server has to be signed by the CA certificate, match the pinned fingerprint or both
+pub fn client_config(ca: Option<&Path>, pin: Option<Fingerprint>) -> io::Result<Arc<ClientConfig>> {
+    let provider = provider();
+
+    let roots = match ca {
+        Some(path) => {
+            let mut roots = RootCertStore::empty();
+            for certificate in load_certificates(path)? {
+                roots.add(certificate).map_err(invalid_data)?;
+            }
+
+            Some(WebPkiServerVerifier::builder_with_provider(Arc::new(roots), provider.clone()).build().map_err(invalid_data)?)
+        },
+        None if pin.is_some() => None,
+        None => return Err(invalid_data("Server can't be verified without a CA certificate or a pinned fingerprint")),
+    };
+
+    let verifier = PinnedVerifier { roots, pin, provider: provider.clone() };
+    let config = ClientConfig::builder_with_provider(provider)
+        .with_safe_default_protocol_versions()
+        .map_err(invalid_data)?
+        .dangerous()
+        .with_custom_certificate_verifier(Arc::new(verifier))
+        .with_no_client_auth();
+
+    Ok(Arc::new(config))
+}
+
+/// Checks the certificate against the CA and the pin, a pin alone is enough for self-signed certificates
+#[derive(Debug)]
+struct PinnedVerifier {
+    roots: Option<Arc<WebPkiServerVerifier>>,
+    pin: Option<Fingerprint>,
+    provider: Arc<CryptoProvider>,
+}
+
+impl ServerCertVerifier for PinnedVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        intermediates: &[CertificateDer<'_>],
+        server_name: &ServerName<'_>,
+        ocsp_response: &[u8],
+        now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        if let Some(roots) = &self.roots {
+            roots.verify_server_cert(end_entity, intermediates, server_name, ocsp_response, now)?;
+        }
+
+        match self.pin {
+            Some(pin) if Fingerprint::of(end_entity) != pin => {
+                Err(rustls::Error::General(format!("Server certificate {} isn't the pinned one", Fingerprint::of(end_entity))))
+            },
+            _ => Ok(ServerCertVerified::assertion()),
+        }
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        certificate: &CertificateDer<'_>,
+        signature: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        crypto::verify_tls12_signature(message, certificate, signature, &self.provider.signature_verification_algorithms)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        certificate: &CertificateDer<'_>,
+        signature: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        crypto::verify_tls13_signature(message, certificate, signature, &self.provider.signature_verification_algorithms)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.provider.signature_verification_algorithms.supported_schemes()
+    }
+}
+
+enum Connection {
+    Client(StreamOwned<ClientConnection, TcpStream>),
+    Server(StreamOwned<ServerConnection, TcpStream>),
+}
+
+impl Connection {
+    fn socket(&self) -> &TcpStream {
+        match self {
+            Self::Client(stream) => &stream.sock,
+            Self::Server(stream) => &stream.sock,
+        }
+    }
+}
+
+/// Encrypted TCP stream. TLS can't be read and written from separate handles like a socket, so the clones share the
+/// connection and reads give it up every `POLL_INTERVAL` to let writes through
+#[derive(Clone)]
+pub struct TlsStream {
+    connection: Arc<Mutex<Connection>>,
+    read_timeout: Arc<Mutex<Option<Duration>>>,
+}
+
+impl TlsStream {
+    fn new(connection: Connection) -> io::Result<Self> {
+        connection.socket().set_read_timeout(Some(POLL_INTERVAL))?;
+
+        Ok(Self {
+            connection: Arc::new(Mutex::new(connection)),
+            read_timeout: Arc::new(Mutex::new(None)),
+        })
+    }
+
+    /// Server end of the accepted stream, the handshake is finished by the first read
+    pub fn accept(stream: TcpStream, config: Arc<ServerConfig>) -> io::Result<Self> {
+        let connection = ServerConnection::new(config).map_err(invalid_data)?;
+        Self::new(Connection::Server(StreamOwned::new(connection, stream)))
+    }
+
+    /// Client end, the handshake is done before returning so that a bad certificate fails right away
+    pub fn connect(stream: TcpStream, config: Arc<ClientConfig>, server_name: &str) -> io::Result<Self> {
+        let server_name = ServerName::try_from(server_name.to_string()).map_err(invalid_data)?;
+        let mut connection = ClientConnection::new(config, server_name).map_err(invalid_data)?;
+
+        let mut stream = stream;
+        stream.set_read_timeout(Some(HANDSHAKE_TIMEOUT))?;
+        while connection.is_handshaking() {
+            connection.complete_io(&mut stream)?;
+        }
+
+        Self::new(Connection::Client(StreamOwned::new(connection, stream)))
+    }
+
+    pub fn peer_addr(&self) -> io::Result<SocketAddr> {
+        self.connection.lock().unwrap().socket().peer_addr()
+    }
+
+    /// Reads fail with `TimedOut` when nothing arrives in time, like they do on a socket
+    pub fn set_read_timeout(&self, timeout: Option<Duration>) {
+        *self.read_timeout.lock().unwrap() = timeout;
+    }
+
+    /// Tells the other end the stream is closing on purpose and shuts down the socket
+    pub fn shutdown(&self) -> io::Result<()> {
+        let mut connection = self.connection.lock().unwrap();
+        match &mut *connection {
+            Connection::Client(stream) => {
+                stream.conn.send_close_notify();
+                // Other end may be gone already, the socket gets shut down either way
+                let _ = stream.flush();
+            },
+            Connection::Server(stream) => {
+                stream.conn.send_close_notify();
+                let _ = stream.flush();
+            },
+        }
+
+        connection.socket().shutdown(Shutdown::Both)
+    }
+}
+
+impl Read for TlsStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let started = Instant::now();
+        let timeout = *self.read_timeout.lock().unwrap();
+
+        loop {
+            // Lock is released between the polls
+            let result = match &mut *self.connection.lock().unwrap() {
+                Connection::Client(stream) => stream.read(buf),
+                Connection::Server(stream) => stream.read(buf),
+            };
+
+            match result {
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock || e.kind() == io::ErrorKind::TimedOut => {
+                    if timeout.is_some_and(|timeout| started.elapsed() >= timeout) {
+                        return Err(e);
+                    }
+                },
+                // Peer that closes without telling is just as gone as one that does
+                Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(0),
+                result => return result,
+            }
+        }
+    }
+}
+
+impl Write for TlsStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match &mut *self.connection.lock().unwrap() {
+            Connection::Client(stream) => stream.write(buf),
+            Connection::Server(stream) => stream.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match &mut *self.connection.lock().unwrap() {
+            Connection::Client(stream) => stream.flush(),
+            Connection::Server(stream) => stream.flush(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fingerprint_format() {
+        let fingerprint = Fingerprint::of(b"certificate");
+        let text = fingerprint.to_string();
+
+        assert_eq!(95, text.len());
+        assert_eq!(fingerprint, text.parse().unwrap());
+        assert_eq!(fingerprint, text.replace(':', "").to_lowercase().parse().unwrap());
+        assert!("AB:CD".parse::<Fingerprint>().is_err());
+        assert!("ZZ".repeat(32).parse::<Fingerprint>().is_err());
+    }
+}
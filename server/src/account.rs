@@ -0,0 +1,20 @@
+use serde::{Deserialize, Serialize};
+
+/// Saved state of a game, what survives restarting the server. The shoe isn't kept, games continue with a fresh one
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone, Default)]
+pub struct Account {
+    pub balance: u32,
+    #[serde(default)]
+    pub last_bet: Option<u32>,
+    /// Round that was in progress when the game was saved
+    #[serde(default)]
+    pub round: Option<Round>,
+}
+
+/// Round in progress, the hands are in compact notation, e.g. "TH 9S"
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct Round {
+    pub bet: u32,
+    pub dealer: String,
+    pub player: String,
+}
@@ -0,0 +1,255 @@
+use network::error::Error;
+use ring::rand::{SecureRandom, SystemRandom};
+use ring::{digest, pbkdf2};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::num::NonZeroU32;
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
+
+/// Rounds of PBKDF2, slow enough to make guessing stolen hashes expensive
+pub const ITERATIONS: u32 = 100_000;
+pub const MIN_PASSWORD_LENGTH: usize = 8;
+/// Wrong passwords in a row before the account is locked
+pub const MAX_FAILURES: u32 = 5;
+pub const LOCKOUT: Duration = Duration::from_secs(5 * 60);
+
+const SALT_LENGTH: usize = 16;
+const TOKEN_LENGTH: usize = 32;
+
+/// Salted PBKDF2-HMAC-SHA256 hash of a password, the iterations are kept so that they can be raised later
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct Credential {
+    iterations: u32,
+    salt: String,
+    hash: String,
+}
+
+impl Credential {
+    pub fn new(password: &str) -> Result<Self, Error> {
+        Self::with_iterations(password, ITERATIONS)
+    }
+
+    pub fn with_iterations(password: &str, iterations: u32) -> Result<Self, Error> {
+        if password.chars().count() < MIN_PASSWORD_LENGTH {
+            return Err(Error::WeakPassword);
+        }
+
+        let iterations = NonZeroU32::new(iterations).ok_or(Error::ServerError)?;
+        let mut salt = [0u8; SALT_LENGTH];
+        SystemRandom::new().fill(&mut salt).map_err(|_| Error::ServerError)?;
+
+        let mut hash = [0u8; digest::SHA256_OUTPUT_LEN];
+        pbkdf2::derive(pbkdf2::PBKDF2_HMAC_SHA256, iterations, &salt, password.as_bytes(), &mut hash);
+
+        Ok(Self { iterations: iterations.get(), salt: to_hex(&salt), hash: to_hex(&hash) })
+    }
+
+    /// Credential of a random password, checked for names without an account so that they take as long as real ones
+    pub fn dummy() -> &'static Self {
+        static DUMMY: OnceLock<Credential> = OnceLock::new();
+        DUMMY.get_or_init(|| {
+            let mut password = [0u8; SALT_LENGTH];
+            let _ = SystemRandom::new().fill(&mut password);
+            // Only fails if the system rng does, then no password can be set either and there's nothing to compare with
+            Self::new(&to_hex(&password)).unwrap_or(Self { iterations: ITERATIONS, salt: String::new(), hash: String::new() })
+        })
+    }
+
+    /// Comparison takes the same time however much of the hash matches
+    pub fn verify(&self, password: &str) -> bool {
+        match (NonZeroU32::new(self.iterations), from_hex(&self.salt), from_hex(&self.hash)) {
+            (Some(iterations), Some(salt), Some(hash)) => {
+                pbkdf2::verify(pbkdf2::PBKDF2_HMAC_SHA256, iterations, &salt, password.as_bytes(), &hash).is_ok()
+            },
+            _ => false,
+        }
+    }
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn from_hex(s: &str) -> Option<Vec<u8>> {
+    if s.len() % 2 != 0 {
+        return None;
+    }
+
+    (0..s.len()).step_by(2).map(|i| s.get(i..i + 2).and_then(|byte| u8::from_str_radix(byte, 16).ok())).collect()
+}
+
+/// Wrong passwords of every account since the last right one, only kept in memory
+#[derive(Default)]
+pub struct Lockouts {
+    failures: HashMap<String, (u32, Instant)>,
+}
+
+impl Lockouts {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Refuses the account while it's locked, the lock opens by itself after `LOCKOUT`
+    pub fn check(&mut self, name: &str, now: Instant) -> Result<(), Error> {
+        match self.failures.get(name) {
+            Some((failures, last)) if *failures >= MAX_FAILURES => {
+                if now.duration_since(*last) < LOCKOUT {
+                    return Err(Error::AccountLocked);
+                }
+
+                self.failures.remove(name);
+                Ok(())
+            },
+            _ => Ok(()),
+        }
+    }
+
+    pub fn failed(&mut self, name: &str, now: Instant) {
+        let failures = self.failures.entry(name.to_string()).or_insert((0, now));
+        *failures = (failures.0 + 1, now);
+    }
+
+    pub fn succeeded(&mut self, name: &str) {
+        self.failures.remove(name);
+    }
+}
+
+struct Session {
+    account: String,
+    /// None while the connection the token was issued to is open
+    expires: Option<Instant>,
+}
+
+impl Session {
+    fn is_valid(&self, now: Instant) -> bool {
+        self.expires.is_none_or(|expires| expires > now)
+    }
+}
+
+/// Tokens that let a player continue from another connection, only kept in memory so a restart ends them all
+pub struct Sessions {
+    ttl: Duration,
+    sessions: HashMap<String, Session>,
+}
+
+impl Sessions {
+    /// Tokens stay valid while their connection is open and for `ttl` after it closes
+    pub fn new(ttl: Duration) -> Self {
+        Self { ttl, sessions: HashMap::new() }
+    }
+
+    pub fn ttl(&self) -> Duration {
+        self.ttl
+    }
+
+    pub fn issue(&mut self, account: &str, now: Instant) -> Result<String, Error> {
+        self.sessions.retain(|_, session| session.is_valid(now));
+
+        let mut token = [0u8; TOKEN_LENGTH];
+        SystemRandom::new().fill(&mut token).map_err(|_| Error::ServerError)?;
+        let token = to_hex(&token);
+
+        self.sessions.insert(token.clone(), Session { account: account.to_string(), expires: None });
+        Ok(token)
+    }
+
+    /// Account of the token, which can't be used again. Resuming gets a new token
+    pub fn take(&mut self, token: &str, now: Instant) -> Result<String, Error> {
+        match self.sessions.remove(token) {
+            Some(session) if session.is_valid(now) => Ok(session.account),
+            _ => Err(Error::InvalidSession),
+        }
+    }
+
+    /// Tokens of the account stop working, other than the one given
+    pub fn revoke(&mut self, account: &str, except: Option<&str>) {
+        self.sessions.retain(|token, session| session.account != account || Some(token.as_str()) == except);
+    }
+
+    /// Connection of the token closed, the player has `ttl` from now on to come back
+    pub fn keep(&mut self, token: &str, now: Instant) {
+        let ttl = self.ttl;
+        if let Some(session) = self.sessions.get_mut(token) {
+            session.expires = Some(now + ttl);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn credential() {
+        let credential = Credential::with_iterations("correct horse", 10).unwrap();
+        assert!(credential.verify("correct horse"));
+        assert!(!credential.verify("correct horsE"));
+
+        // Same password gets a different salt every time
+        let other = Credential::with_iterations("correct horse", 10).unwrap();
+        assert_ne!(credential.hash, other.hash);
+        assert!(other.verify("correct horse"));
+
+        assert_eq!(Some(Error::WeakPassword), Credential::with_iterations("short", 10).err());
+        assert_eq!(Some(vec![0x0a, 0xff]), from_hex(&to_hex(&[0x0a, 0xff])));
+        assert_eq!(None, from_hex("abc"));
+    }
+
+    #[test]
+    fn lockout() {
+        let mut lockouts = Lockouts::new();
+        let now = Instant::now();
+
+        for _ in 0..MAX_FAILURES - 1 {
+            lockouts.failed("alice", now);
+        }
+        assert_eq!(Ok(()), lockouts.check("alice", now));
+        lockouts.failed("alice", now);
+        assert_eq!(Err(Error::AccountLocked), lockouts.check("alice", now));
+        assert_eq!(Ok(()), lockouts.check("bob", now));
+
+        // Lock opens after a while and the count starts over
+        assert_eq!(Ok(()), lockouts.check("alice", now + LOCKOUT));
+        lockouts.failed("alice", now + LOCKOUT);
+        assert_eq!(Ok(()), lockouts.check("alice", now + LOCKOUT));
+
+        lockouts.succeeded("alice");
+        assert!(lockouts.failures.is_empty());
+    }
+
+    #[test]
+    fn sessions() {
+        let ttl = Duration::from_secs(60);
+        let mut sessions = Sessions::new(ttl);
+        let now = Instant::now();
+
+        let token = sessions.issue("alice", now).unwrap();
+        assert_eq!(TOKEN_LENGTH * 2, token.len());
+        assert_eq!(Ok("alice".to_string()), sessions.take(&token, now));
+        assert_eq!(Err(Error::InvalidSession), sessions.take(&token, now));
+
+        // Closing the connection gives the full time to come back
+        let token = sessions.issue("bob", now).unwrap();
+        sessions.keep(&token, now + ttl / 2);
+        assert_eq!(Ok("bob".to_string()), sessions.take(&token, now + ttl));
+
+        let token = sessions.issue("carol", now).unwrap();
+        sessions.keep(&token, now);
+        assert_eq!(Err(Error::InvalidSession), sessions.take(&token, now + ttl));
+
+        // Tokens of open connections outlive the purge of expired ones
+        let token = sessions.issue("dave", now).unwrap();
+        sessions.issue("erin", now + ttl * 2).unwrap();
+        sessions.keep(&token, now + ttl * 2);
+        assert_eq!(Ok("dave".to_string()), sessions.take(&token, now + ttl * 2));
+
+        let kept = sessions.issue("frank", now).unwrap();
+        let revoked = sessions.issue("frank", now).unwrap();
+        let other = sessions.issue("gina", now).unwrap();
+        sessions.revoke("frank", Some(&kept));
+        assert_eq!(Err(Error::InvalidSession), sessions.take(&revoked, now));
+        assert_eq!(Ok("frank".to_string()), sessions.take(&kept, now));
+        assert_eq!(Ok("gina".to_string()), sessions.take(&other, now));
+    }
+}
@@ -1,4 +1,10 @@
+use crate::account::{Account, Round};
+use crate::count::{BetTracker, Count, CountingSystem};
 use crate::deck;
+use crate::odds::{self, DealerOdds};
+use log::*;
+use network::error::Error;
+use network::server_message::{Action, LegalAction};
 use std::fmt;
 
 #[derive(PartialEq)]
@@ -13,6 +19,15 @@ pub enum Winner {
     Draw
 }
 
+/// What happens to a round the player walked away from
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum IdlePolicy {
+    /// Player stands on the hand they have and the dealer plays out the round
+    Stand,
+    /// Bet is lost without the dealer drawing any cards
+    Forfeit,
+}
+
 pub enum HitResult {
     Continue,
     Bust
@@ -30,6 +45,34 @@ impl fmt::Display for Winner {
     }
 }
 
+/// Why a saved game can't be continued
+#[derive(Debug, PartialEq)]
+pub enum RestoreError {
+    Card(deck::ParseCardError),
+    /// Bet of the round is more than the balance can cover
+    Bet { bet: u32, balance: u32 },
+    /// Round doesn't have cards in both hands
+    EmptyHand,
+}
+
+impl fmt::Display for RestoreError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Card(e) => write!(f, "{}", e),
+            Self::Bet { bet, balance } => write!(f, "Bet {} is more than the balance {}", bet, balance),
+            Self::EmptyHand => write!(f, "Round has an empty hand"),
+        }
+    }
+}
+
+impl std::error::Error for RestoreError {}
+
+impl From<deck::ParseCardError> for RestoreError {
+    fn from(error: deck::ParseCardError) -> Self {
+        Self::Card(error)
+    }
+}
+
 type Hand = Vec<deck::Card>;
 
 pub struct Blackjack {
@@ -38,11 +81,17 @@ pub struct Blackjack {
     dealer_hand: Hand,
     player_hand: Hand,
     bet: u32,
+    /// Bet the last round was started with, before any doubling
+    last_bet: Option<u32>,
     balance: u32,
+    bets: BetTracker,
+    /// Shuffles of the shoe before the hole card was dealt, the shoe's count only has the hole card if it's the same
+    hole_card_shuffles: u32,
 }
 
 impl deck::CardValue {
-    fn value(&self) -> u8 {
+    /// Points the card is worth, aces are always counted as 1
+    pub(crate) fn value(&self) -> u8 {
         match self {
             Self::Ace => 1,
             Self::Two => 2,
@@ -62,65 +111,145 @@ impl deck::CardValue {
 }
 
 impl Blackjack {
-    pub fn new() -> Self {
+    pub fn new() -> Result<Self, deck::ShoeError> {
+        Ok(Self::with_shoe(deck::Shoe::new(3)?))
+    }
+
+    /// Game dealt from the given shoe, e.g. a stacked shoe to test rules or replay a hand
+    pub fn with_shoe(shoe: deck::Shoe) -> Self {
         Self {
-            shoe: deck::Shoe::new(3).unwrap(),
+            shoe,
             state: State::Finished,
             dealer_hand: Vec::new(),
             player_hand: Vec::new(),
             bet: 0,
-            balance: 0
+            last_bet: None,
+            balance: 0,
+            bets: BetTracker::new(),
+            hole_card_shuffles: 0,
+        }
+    }
+
+    /// Game continued from the saved account, an unfinished round goes on with the same hands
+    /// Cards of the hands are taken out of the shoe so that the counts and odds know they're gone
+    /// Rounds the game couldn't have been left in, e.g. from an edited file, are refused
+    pub fn restore(shoe: deck::Shoe, account: &Account) -> Result<Self, RestoreError> {
+        let mut blackjack = Self::with_shoe(shoe);
+        blackjack.balance = account.balance;
+        blackjack.last_bet = account.last_bet;
+
+        if let Some(round) = &account.round {
+            if round.bet > account.balance {
+                return Err(RestoreError::Bet { bet: round.bet, balance: account.balance });
+            }
+
+            blackjack.dealer_hand = deck::parse_cards(&round.dealer)?;
+            blackjack.player_hand = deck::parse_cards(&round.player)?;
+            if blackjack.dealer_hand.is_empty() || blackjack.player_hand.is_empty() {
+                return Err(RestoreError::EmptyHand);
+            }
+
+            blackjack.shoe.take_out(&blackjack.dealer_hand);
+            blackjack.shoe.take_out(&blackjack.player_hand);
+            blackjack.hole_card_shuffles = blackjack.shoe.shuffles();
+            blackjack.bet = round.bet;
+            blackjack.state = State::Ongoing;
         }
+
+        Ok(blackjack)
+    }
+
+    /// What has to be saved for the game to be restored later
+    pub fn account(&self) -> Account {
+        let round = match self.state {
+            State::Ongoing => Some(Round {
+                bet: self.bet,
+                dealer: deck::format_cards(&self.dealer_hand),
+                player: deck::format_cards(&self.player_hand),
+            }),
+            State::Finished => None,
+        };
+
+        Account { balance: self.balance, last_bet: self.last_bet, round }
     }
 
     pub fn balance(&self) -> u32 {
         self.balance
     }
 
-    pub fn deposit(&mut self, deposit: u32) -> Result<(), String> {
+    pub fn deposit(&mut self, deposit: u32) -> Result<(), Error> {
         if deposit == 0 {
-            return Err("Deposit has to be bigger than 0".to_string());
+            return Err(Error::NonPositiveAmount);
         }
 
-        self.balance += deposit;
+        self.balance = self.balance.checked_add(deposit).ok_or(Error::BalanceLimit)?;
 
         Ok(())
     }
 
-    pub fn start(&mut self, bet: u32) -> Result<(), String> {
+    pub fn start(&mut self, bet: u32) -> Result<(), Error> {
         if self.state == State::Ongoing {
-            return Err("This instance is already running".to_string());
+            return Err(Error::RoundInProgress);
         }
 
         if bet > self.balance {
-            return Err(format!("Not enough balance ({}) to accept the bet ({})", self.balance, bet));
+            return Err(Error::InsufficientBalance { balance: self.balance, bet });
         }
 
-        self.bet = bet;
+        // Count the player could know about when deciding on the bet
+        let true_count = self.shoe.counter().count(CountingSystem::HiLo).true_count;
 
         self.dealer_hand.clear();
         self.player_hand.clear();
 
-        self.state = State::Ongoing;
+        for round in 0..2 {
+            if round == 1 {
+                self.hole_card_shuffles = self.shoe.shuffles();
+            }
+
+            let dealt = Self::add_card(&mut self.shoe, &mut self.dealer_hand)
+                .and_then(|_| Self::add_card(&mut self.shoe, &mut self.player_hand));
 
-        for _ in 0..2 {
-            Self::add_card(&mut self.shoe, &mut self.dealer_hand);
-            Self::add_card(&mut self.shoe, &mut self.player_hand);
+            // Don't leave a half dealt round behind, the game can't be continued from it anyway
+            if let Err(e) = dealt {
+                self.dealer_hand.clear();
+                self.player_hand.clear();
+                return Err(Error::from(e));
+            }
         }
 
+        self.bet = bet;
+        self.last_bet = Some(bet);
+        self.bets.record(true_count, bet);
+        self.state = State::Ongoing;
+        self.log_hands("Dealt");
+
         Ok(())
     }
 
-    pub fn hit(&mut self) -> Result<HitResult, String> {
+    /// Starts a round with the bet the last one was started with
+    pub fn rebet(&mut self) -> Result<u32, Error> {
+        let bet = self.last_bet.ok_or(Error::NoPreviousBet)?;
+        self.start(bet)?;
+
+        Ok(bet)
+    }
+
+    pub fn last_bet(&self) -> Option<u32> {
+        self.last_bet
+    }
+
+    pub fn hit(&mut self) -> Result<HitResult, Error> {
         if self.state != State::Ongoing {
-            return Err("Instance not ongoing".to_string());
+            return Err(Error::NoRoundInProgress);
         }
 
-        Self::add_card(&mut self.shoe, &mut self.player_hand);
+        Self::add_card(&mut self.shoe, &mut self.player_hand).map_err(Error::from)?;
 
         if Self::hand_value(&self.player_hand) > 21 {
             self.balance -= self.bet;
             self.state = State::Finished;
+            self.log_hands("Player bust");
             return Ok(HitResult::Bust);
         }
 
@@ -128,24 +257,25 @@ impl Blackjack {
     }
 
     // Can't use &mut self here since it gets called from other mutable self contexts, and you cant borrow something as mutable multiple times
-    fn add_card(shoe: &mut deck::Shoe, hand: &mut Hand) -> deck::Card {
-        let card = shoe.pull_card();
+    fn add_card(shoe: &mut deck::Shoe, hand: &mut Hand) -> Result<deck::Card, deck::ShoeError> {
+        let card = shoe.pull_card()?;
 
         hand.push(card.clone());
 
-        card
+        Ok(card)
     }
 
-    pub fn stand(&mut self) -> Result<Winner, String> {
+    pub fn stand(&mut self) -> Result<Winner, Error> {
         if self.state != State::Ongoing {
-            return Err("Instance not ongoing".to_string());
+            return Err(Error::NoRoundInProgress);
         }
 
         let player_value = Self::hand_value(&self.player_hand);
         let mut dealer_value = Self::hand_value(&self.dealer_hand);
 
         while dealer_value < player_value && dealer_value < 16 {
-            let card = Self::add_card(&mut self.shoe, &mut self.dealer_hand);
+            // State stays ongoing on failure so the player can try to stand again
+            let card = Self::add_card(&mut self.shoe, &mut self.dealer_hand).map_err(Error::from)?;
             dealer_value += card.value().value();
         }
 
@@ -161,10 +291,173 @@ impl Blackjack {
         };
 
         self.state = State::Finished;
+        self.log_hands(&winner.to_string());
 
         Ok(winner)
     }
 
+    /// Actions the player can take right now and what each takes from the balance, empty when there's no round
+    /// Split is never legal since this table doesn't deal split hands, and neither is insurance since aces count as 1
+    /// so the dealer can't have a blackjack to insure against
+    pub fn legal_actions(&self) -> Vec<LegalAction> {
+        if self.state != State::Ongoing {
+            return Vec::new();
+        }
+
+        let mut actions = vec![LegalAction { action: Action::Hit, cost: 0 }, LegalAction { action: Action::Stand, cost: 0 }];
+        if self.player_hand.len() == 2 {
+            if self.double_bet().is_ok() {
+                actions.push(LegalAction { action: Action::Double, cost: self.bet });
+            }
+            actions.push(LegalAction { action: Action::Surrender, cost: self.surrender_cost() });
+        }
+
+        actions
+    }
+
+    /// Bet after doubling, if the hand can still be doubled and the balance covers it
+    fn double_bet(&self) -> Result<u32, Error> {
+        if self.state != State::Ongoing {
+            return Err(Error::NoRoundInProgress);
+        }
+
+        if self.player_hand.len() != 2 {
+            return Err(Error::ActionNotAllowed);
+        }
+
+        match self.bet.checked_mul(2) {
+            Some(bet) if bet <= self.balance => Ok(bet),
+            _ => Err(Error::InsufficientBalance { balance: self.balance, bet: self.bet.saturating_mul(2) }),
+        }
+    }
+
+    /// Half of the bet is lost, rounded in the house's favour
+    fn surrender_cost(&self) -> u32 {
+        self.bet - self.bet / 2
+    }
+
+    /// Doubles the bet, deals one more card and stands. Dealer doesn't draw if the card busts the hand
+    pub fn double(&mut self) -> Result<Winner, Error> {
+        let bet = self.double_bet()?;
+
+        Self::add_card(&mut self.shoe, &mut self.player_hand)?;
+        self.bet = bet;
+
+        if Self::is_bust(&self.player_hand) {
+            self.balance -= self.bet;
+            self.state = State::Finished;
+            self.log_hands("Player bust after doubling");
+            return Ok(Winner::Dealer);
+        }
+
+        self.stand()
+    }
+
+    /// Player gives up the first two cards and gets half of the bet back
+    pub fn surrender(&mut self) -> Result<(), Error> {
+        if self.state != State::Ongoing {
+            return Err(Error::NoRoundInProgress);
+        }
+
+        if self.player_hand.len() != 2 {
+            return Err(Error::ActionNotAllowed);
+        }
+
+        self.balance -= self.surrender_cost();
+        self.state = State::Finished;
+        self.log_hands("Player surrendered");
+
+        Ok(())
+    }
+
+    /// Player gives up the round and loses the bet
+    pub fn forfeit(&mut self) -> Result<(), Error> {
+        if self.state != State::Ongoing {
+            return Err(Error::NoRoundInProgress);
+        }
+
+        self.balance -= self.bet;
+        self.state = State::Finished;
+        self.log_hands("Player forfeited");
+
+        Ok(())
+    }
+
+    /// Ends the round of a player that is gone, returns None if there was no round to end
+    pub fn abandon(&mut self, policy: IdlePolicy) -> Result<Option<Winner>, Error> {
+        if self.state != State::Ongoing {
+            return Ok(None);
+        }
+
+        match policy {
+            IdlePolicy::Stand => self.stand().map(Some),
+            IdlePolicy::Forfeit => self.forfeit().map(|_| Some(Winner::Dealer)),
+        }
+    }
+
+    fn log_hands(&self, event: &str) {
+        debug!(
+            "{}, dealer: {}, player: {}",
+            event,
+            deck::format_cards(&self.dealer_hand),
+            deck::format_cards(&self.player_hand)
+        );
+    }
+
+    /// Count of the cards the player has seen dealt from the shoe, for training card counting
+    /// The dealer's hole card is left out until the round is over
+    pub fn count(&self, system: CountingSystem) -> Count {
+        match self.dealer_hand.get(1) {
+            Some(hole_card) if self.state == State::Ongoing && self.shoe.shuffles() == self.hole_card_shuffles => {
+                self.shoe.counter().count_hiding(system, std::slice::from_ref(hole_card))
+            },
+            _ => self.shoe.counter().count(system),
+        }
+    }
+
+    /// Times the shoe has been shuffled, see `Shoe::shuffles`
+    pub fn shuffles(&self) -> u32 {
+        self.shoe.shuffles()
+    }
+
+    /// Player's bets go up and down with the Hi-Lo true count
+    pub fn is_betting_with_count(&self) -> bool {
+        self.bets.is_suspicious()
+    }
+
+    /// Odds of the dealer's final total given the upcard, assuming the hole card is any of the cards not seen yet
+    pub fn dealer_odds(&self) -> Result<DealerOdds, Error> {
+        if self.state != State::Ongoing {
+            return Err(Error::NoRoundInProgress);
+        }
+
+        let upcard = self.dealer_hand[0].value();
+        let mut composition = self.shoe.composition();
+        // Shoe that was reshuffled since the hole card was dealt has it back already
+        if self.shoe.shuffles() == self.hole_card_shuffles {
+            for card in self.dealer_hand.iter().skip(1) {
+                composition.add(card.value());
+            }
+        }
+
+        // Dealer draws while behind the player and under 16, see stand()
+        let stand_on = Self::hand_value(&self.player_hand).min(16);
+
+        Ok(odds::dealer_odds(&composition, upcard, stand_on))
+    }
+
+    pub fn dealer_upcard(&self) -> Option<&deck::Card> {
+        self.dealer_hand.first()
+    }
+
+    pub fn player_hand(&self) -> &[deck::Card] {
+        &self.player_hand
+    }
+
+    pub fn dealer_hand(&self) -> &[deck::Card] {
+        &self.dealer_hand
+    }
+
     pub fn player_total(&self) -> u8 {
         Self::hand_value(&self.player_hand)
     }
@@ -178,8 +471,201 @@ impl Blackjack {
     }
 
     fn is_bust(hand: &Hand) -> bool {
-        let total = Self::hand_value(&hand);
+        let total = Self::hand_value(hand);
 
         total > 21
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::deck::{self, Shoe};
+
+    /// Game with the balance deposited and a round started, cards are dealt dealer first
+    fn started(cards: &str, balance: u32, bet: u32) -> Blackjack {
+        let mut blackjack = Blackjack::with_shoe(Shoe::stacked(deck::parse_cards(cards).unwrap()));
+        blackjack.deposit(balance).unwrap();
+        blackjack.start(bet).unwrap();
+        blackjack
+    }
+
+    #[test]
+    fn abandon_stands() {
+        // Dealer has 12 and draws a ten against the player's 18
+        let mut blackjack = started("TH 9S 2D 9C KD", 100, 10);
+
+        assert!(matches!(blackjack.abandon(IdlePolicy::Stand), Ok(Some(Winner::Player))));
+        assert_eq!(110, blackjack.balance());
+        assert_eq!(3, blackjack.dealer_hand().len());
+    }
+
+    #[test]
+    fn count_hides_hole_card() {
+        // Hole card is the two of diamonds, a +1 in Hi-Lo
+        let mut blackjack = started("TH 9S 2D 9C KD", 100, 10);
+        assert_eq!(-1, blackjack.count(CountingSystem::HiLo).running);
+        assert_eq!(0, blackjack.shoe.counter().count(CountingSystem::HiLo).running);
+
+        // Ten the dealer draws cancels out the revealed hole card
+        blackjack.stand().unwrap();
+        assert_eq!(-1, blackjack.count(CountingSystem::HiLo).running);
+    }
+
+    #[test]
+    fn dealer_odds_after_reshuffle() {
+        let mut blackjack = Blackjack::with_shoe(Shoe::new(1).unwrap());
+        blackjack.deposit(1000).unwrap();
+        // Naturals end the round right away
+        while blackjack.state != State::Ongoing {
+            blackjack.start(1).unwrap();
+        }
+
+        let shuffles = blackjack.shoe.shuffles();
+        while blackjack.shoe.shuffles() == shuffles {
+            blackjack.shoe.pull_card().unwrap();
+        }
+
+        // Hole card is in the reshuffled shoe, it isn't added a second time
+        let upcard = blackjack.dealer_hand[0].value();
+        let stand_on = Blackjack::hand_value(&blackjack.player_hand).min(16);
+        let expected = odds::dealer_odds(&blackjack.shoe.composition(), upcard, stand_on);
+        assert_eq!(expected, blackjack.dealer_odds().unwrap());
+    }
+
+    #[test]
+    fn abandon_forfeits() {
+        let mut blackjack = started("TH 9S 2D 9C KD", 100, 10);
+
+        assert!(matches!(blackjack.abandon(IdlePolicy::Forfeit), Ok(Some(Winner::Dealer))));
+        assert_eq!(90, blackjack.balance());
+        assert_eq!(2, blackjack.dealer_hand().len());
+        assert!(blackjack.forfeit().is_err());
+    }
+
+    #[test]
+    fn rule_errors() {
+        let mut blackjack = started("TH 9S 2D 9C KD", 100, 10);
+
+        assert_eq!(Some(Error::RoundInProgress), blackjack.start(10).err());
+        blackjack.stand().unwrap();
+        assert_eq!(Some(Error::NoRoundInProgress), blackjack.hit().err());
+        assert_eq!(Some(Error::InsufficientBalance { balance: 110, bet: 200 }), blackjack.start(200).err());
+        assert_eq!(Some(Error::NonPositiveAmount), blackjack.deposit(0).err());
+        assert_eq!(Some(Error::BalanceLimit), blackjack.deposit(u32::MAX).err());
+        assert_eq!(110, blackjack.balance());
+    }
+
+    #[test]
+    fn legal_actions() {
+        let mut blackjack = started("TH 9S 2D 2C 3H KD", 100, 15);
+        let actions = |blackjack: &Blackjack| -> Vec<(Action, u32)> {
+            blackjack.legal_actions().iter().map(|legal| (legal.action, legal.cost)).collect()
+        };
+        assert_eq!(
+            vec![(Action::Hit, 0), (Action::Stand, 0), (Action::Double, 15), (Action::Surrender, 8)],
+            actions(&blackjack)
+        );
+
+        blackjack.hit().unwrap();
+        assert_eq!(vec![(Action::Hit, 0), (Action::Stand, 0)], actions(&blackjack));
+        assert_eq!(Some(Error::ActionNotAllowed), blackjack.double().err());
+        assert_eq!(Some(Error::ActionNotAllowed), blackjack.surrender().err());
+
+        blackjack.stand().unwrap();
+        assert!(blackjack.legal_actions().is_empty());
+        assert_eq!(Some(Error::NoRoundInProgress), blackjack.surrender().err());
+    }
+
+    #[test]
+    fn double_needs_balance() {
+        let mut blackjack = started("TH 9S 2D 9C KD", 15, 10);
+
+        assert!(!blackjack.legal_actions().iter().any(|legal| legal.action == Action::Double));
+        assert_eq!(Some(Error::InsufficientBalance { balance: 15, bet: 20 }), blackjack.double().err());
+    }
+
+    #[test]
+    fn double() {
+        // Player's 11 gets a ten, the dealer's 12 draws a nine
+        let mut blackjack = started("TH 9S 2D 2C KD 9H", 100, 10);
+
+        assert!(matches!(blackjack.double(), Ok(Winner::Player)));
+        assert_eq!(120, blackjack.balance());
+        assert_eq!(3, blackjack.player_hand().len());
+        assert!(blackjack.legal_actions().is_empty());
+    }
+
+    #[test]
+    fn double_bust() {
+        let mut blackjack = started("TH 9S 2D 9C KD", 100, 10);
+
+        assert!(matches!(blackjack.double(), Ok(Winner::Dealer)));
+        assert_eq!(80, blackjack.balance());
+        assert_eq!(2, blackjack.dealer_hand().len());
+    }
+
+    #[test]
+    fn surrender() {
+        let mut blackjack = started("TH 9S 2D 9C", 100, 15);
+
+        blackjack.surrender().unwrap();
+        assert_eq!(92, blackjack.balance());
+        assert!(blackjack.hit().is_err());
+    }
+
+    #[test]
+    fn rebet() {
+        let mut blackjack = Blackjack::with_shoe(Shoe::stacked(deck::parse_cards("TH 9S 2D 2C KD 9H 5H 6S 7D 8C").unwrap()));
+        blackjack.deposit(100).unwrap();
+        assert_eq!(Some(Error::NoPreviousBet), blackjack.rebet().err());
+
+        // Doubling doesn't change the bet that gets repeated
+        blackjack.start(10).unwrap();
+        blackjack.double().unwrap();
+        assert_eq!(Some(10), blackjack.last_bet());
+        assert_eq!(Ok(10), blackjack.rebet());
+        assert_eq!(Some(Error::RoundInProgress), blackjack.rebet().err());
+    }
+
+    #[test]
+    fn restore() {
+        let blackjack = started("TH 9S 2D 2C KD", 100, 10);
+        let account = blackjack.account();
+        assert_eq!("9S 2C", account.round.as_ref().unwrap().player);
+
+        // Restored round continues from the same hands with the new shoe
+        let mut restored = Blackjack::restore(Shoe::stacked(deck::parse_cards("KD").unwrap()), &account).unwrap();
+        assert_eq!(account, restored.account());
+        assert!(matches!(restored.hit(), Ok(HitResult::Continue)));
+        assert_eq!(21, restored.player_total());
+
+        // Cards of the hands aren't in the new shoe anymore and the visible ones are counted
+        let restored = Blackjack::restore(Shoe::new(1).unwrap(), &account).unwrap();
+        let composition = restored.shoe.composition();
+        assert_eq!(48, composition.total());
+        assert_eq!(2, composition.count(&deck::CardValue::Two));
+        assert_eq!(3, composition.count(&deck::CardValue::Nine));
+        assert_eq!(0, restored.count(CountingSystem::HiLo).running);
+
+        // Rounds that can't come from a real game are refused
+        let broken = |bet: u32, dealer: &str, player: &str| {
+            let round = Round { bet, dealer: dealer.to_string(), player: player.to_string() };
+            Blackjack::restore(Shoe::new(1).unwrap(), &Account { balance: 10, last_bet: Some(bet), round: Some(round) }).err()
+        };
+        assert_eq!(Some(RestoreError::Bet { bet: 20, balance: 10 }), broken(20, "TH 9S", "2D 2C"));
+        assert_eq!(Some(RestoreError::EmptyHand), broken(10, "", "2D 2C"));
+        assert_eq!(Some(RestoreError::EmptyHand), broken(10, "TH 9S", ""));
+        assert!(matches!(broken(10, "TH 9S", "2D XX"), Some(RestoreError::Card(_))));
+
+        let finished = Blackjack::restore(Shoe::stacked(Vec::new()), &Account { balance: 5, last_bet: None, round: None }).unwrap();
+        assert!(finished.legal_actions().is_empty());
+        assert_eq!(5, finished.balance());
+    }
+
+    #[test]
+    fn abandon_without_round() {
+        let mut blackjack = Blackjack::with_shoe(Shoe::stacked(Vec::new()));
+        assert!(matches!(blackjack.abandon(IdlePolicy::Forfeit), Ok(None)));
+    }
+}
@@ -0,0 +1,306 @@
+use crate::deck::{Card, CardValue};
+use std::collections::VecDeque;
+use std::fmt;
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum CountingSystem {
+    HiLo,
+    /// Knock-out, an unbalanced system so the running count starts below zero for multiple decks
+    Ko,
+    OmegaII,
+    Zen,
+}
+
+impl fmt::Display for CountingSystem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let text = match self {
+            Self::HiLo => "Hi-Lo",
+            Self::Ko => "KO",
+            Self::OmegaII => "Omega II",
+            Self::Zen => "Zen",
+        };
+
+        write!(f, "{}", text)
+    }
+}
+
+impl CountingSystem {
+    pub const ALL: [CountingSystem; 4] = [Self::HiLo, Self::Ko, Self::OmegaII, Self::Zen];
+
+    /// Value the running count changes by when the card is seen
+    pub fn tag(self, value: &CardValue) -> i32 {
+        use CardValue::*;
+
+        match self {
+            Self::HiLo => match value {
+                Two | Three | Four | Five | Six => 1,
+                Seven | Eight | Nine => 0,
+                Ten | Jack | Queen | King | Ace => -1,
+            },
+            Self::Ko => match value {
+                Two | Three | Four | Five | Six | Seven => 1,
+                Eight | Nine => 0,
+                Ten | Jack | Queen | King | Ace => -1,
+            },
+            Self::OmegaII => match value {
+                Two | Three | Seven => 1,
+                Four | Five | Six => 2,
+                Eight | Ace => 0,
+                Nine => -1,
+                Ten | Jack | Queen | King => -2,
+            },
+            Self::Zen => match value {
+                Two | Three | Seven => 1,
+                Four | Five | Six => 2,
+                Eight | Nine => 0,
+                Ten | Jack | Queen | King => -2,
+                Ace => -1,
+            },
+        }
+    }
+
+    /// Running count of a freshly shuffled shoe
+    fn initial_count(self, deck_count: usize) -> i32 {
+        match self {
+            Self::Ko => 4 - 4 * deck_count as i32,
+            _ => 0,
+        }
+    }
+
+    fn index(self) -> usize {
+        match self {
+            Self::HiLo => 0,
+            Self::Ko => 1,
+            Self::OmegaII => 2,
+            Self::Zen => 3,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Count {
+    pub running: i32,
+    /// Running count divided by the number of decks left in the shoe
+    pub true_count: f64,
+}
+
+/// Keeps the running counts of every counting system as the shoe deals its cards
+pub struct CardCounter {
+    running: [i32; 4],
+    deck_size: usize,
+    cards_remaining: usize,
+}
+
+impl CardCounter {
+    pub fn new(deck_size: usize) -> Self {
+        Self {
+            running: [0; 4],
+            deck_size,
+            cards_remaining: 0,
+        }
+    }
+
+    /// Starts counting from the beginning for a shoe with the given number of cards
+    pub fn reset(&mut self, cards: usize) {
+        let deck_count = ((cards as f64 / self.deck_size as f64).round() as usize).max(1);
+
+        for system in CountingSystem::ALL.iter() {
+            self.running[system.index()] = system.initial_count(deck_count);
+        }
+
+        self.cards_remaining = cards;
+    }
+
+    pub fn card_dealt(&mut self, card: &Card) {
+        for system in CountingSystem::ALL.iter() {
+            self.running[system.index()] += system.tag(card.value());
+        }
+
+        self.cards_remaining = self.cards_remaining.saturating_sub(1);
+    }
+
+    pub fn count(&self, system: CountingSystem) -> Count {
+        self.count_hiding(system, &[])
+    }
+
+    /// Count as if the cards were still in the shoe, for dealt cards that are face down
+    pub fn count_hiding(&self, system: CountingSystem, hidden: &[Card]) -> Count {
+        let running = self.running[system.index()] - hidden.iter().map(|card| system.tag(card.value())).sum::<i32>();
+        let cards_remaining = self.cards_remaining + hidden.len();
+        let decks_remaining = cards_remaining as f64 / self.deck_size as f64;
+
+        // Last card of the shoe has been dealt, there's nothing to divide with
+        let true_count = if cards_remaining == 0 {
+            running as f64
+        } else {
+            running as f64 / decks_remaining
+        };
+
+        Count { running, true_count }
+    }
+}
+
+/// Remembers the bets a player made and the true count at the time, to spot players betting with the count
+pub struct BetTracker {
+    rounds: VecDeque<(f64, u32)>,
+}
+
+impl BetTracker {
+    /// How many of the latest rounds are used
+    const HISTORY: usize = 100;
+    /// Minimum number of rounds before anything is reported
+    const MINIMUM_ROUNDS: usize = 20;
+    /// Correlation between true count and bet that is considered suspicious
+    const SUSPICIOUS_CORRELATION: f64 = 0.6;
+    /// Ratio between the largest and smallest bet that is considered suspicious
+    const SUSPICIOUS_SPREAD: u32 = 4;
+
+    pub fn new() -> Self {
+        Self {
+            rounds: VecDeque::with_capacity(Self::HISTORY),
+        }
+    }
+
+    pub fn record(&mut self, true_count: f64, bet: u32) {
+        if self.rounds.len() == Self::HISTORY {
+            self.rounds.pop_front();
+        }
+
+        self.rounds.push_back((true_count, bet));
+    }
+
+    /// Pearson correlation between the true count and the bet, None until there are enough rounds to tell
+    pub fn correlation(&self) -> Option<f64> {
+        if self.rounds.len() < Self::MINIMUM_ROUNDS {
+            return None;
+        }
+
+        let n = self.rounds.len() as f64;
+        let mean_count = self.rounds.iter().map(|(count, _)| count).sum::<f64>() / n;
+        let mean_bet = self.rounds.iter().map(|(_, bet)| *bet as f64).sum::<f64>() / n;
+
+        let (mut covariance, mut count_variance, mut bet_variance) = (0.0, 0.0, 0.0);
+        for (count, bet) in self.rounds.iter() {
+            let count = count - mean_count;
+            let bet = *bet as f64 - mean_bet;
+            covariance += count * bet;
+            count_variance += count * count;
+            bet_variance += bet * bet;
+        }
+
+        // Flat bets or a flat count can't be correlated with anything
+        if count_variance == 0.0 || bet_variance == 0.0 {
+            return Some(0.0);
+        }
+
+        Some(covariance / (count_variance.sqrt() * bet_variance.sqrt()))
+    }
+
+    /// Bets go up with the count and the difference between the smallest and largest bet is big
+    pub fn is_suspicious(&self) -> bool {
+        let correlation = match self.correlation() {
+            Some(correlation) => correlation,
+            None => return false,
+        };
+
+        let min = self.rounds.iter().map(|(_, bet)| *bet).min().unwrap_or(0).max(1);
+        let max = self.rounds.iter().map(|(_, bet)| *bet).max().unwrap_or(0);
+
+        correlation >= Self::SUSPICIOUS_CORRELATION && max >= min * Self::SUSPICIOUS_SPREAD
+    }
+}
+
+impl Default for BetTracker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::deck::{self, Deck};
+
+    #[test]
+    fn balanced_systems_sum_to_zero() {
+        let deck = Deck::new();
+
+        for system in [CountingSystem::HiLo, CountingSystem::OmegaII, CountingSystem::Zen].iter() {
+            let sum: i32 = deck.cards().iter().map(|card| system.tag(card.value())).sum();
+            assert_eq!(0, sum, "{}", system);
+        }
+    }
+
+    #[test]
+    fn ko_counts_up_from_initial_running_count() {
+        let deck = Deck::new();
+        let mut counter = CardCounter::new(52);
+        counter.reset(52 * 6);
+        assert_eq!(-20, counter.count(CountingSystem::Ko).running);
+
+        for _ in 0..6 {
+            for card in deck.cards() {
+                counter.card_dealt(card);
+            }
+        }
+
+        assert_eq!(4, counter.count(CountingSystem::Ko).running);
+        assert_eq!(0, counter.count(CountingSystem::HiLo).running);
+    }
+
+    #[test]
+    fn running_and_true_count() {
+        let mut counter = CardCounter::new(52);
+        counter.reset(52 * 2);
+
+        for card in deck::parse_cards("2H 5S 6D KC 4H 3S").unwrap() {
+            counter.card_dealt(&card);
+        }
+
+        // 98 cards left is 1.88 decks
+        let count = counter.count(CountingSystem::HiLo);
+        assert_eq!(4, count.running);
+        assert!((count.true_count - 4.0 / (98.0 / 52.0)).abs() < 1e-9);
+
+        assert_eq!(6, counter.count(CountingSystem::OmegaII).running);
+        assert_eq!(6, counter.count(CountingSystem::Zen).running);
+    }
+
+    #[test]
+    fn bet_tracker_needs_enough_rounds() {
+        let mut tracker = BetTracker::new();
+
+        for i in 0..BetTracker::MINIMUM_ROUNDS - 1 {
+            tracker.record(i as f64, i as u32 * 10 + 10);
+        }
+
+        assert_eq!(None, tracker.correlation());
+        assert!(!tracker.is_suspicious());
+    }
+
+    #[test]
+    fn bet_tracker_spots_betting_with_the_count() {
+        let mut tracker = BetTracker::new();
+
+        for i in 0..30 {
+            let true_count = (i % 6) as f64 - 1.0;
+            let bet = if true_count >= 2.0 { 100 } else { 10 };
+            tracker.record(true_count, bet);
+        }
+
+        assert!(tracker.correlation().unwrap() > 0.6);
+        assert!(tracker.is_suspicious());
+    }
+
+    #[test]
+    fn bet_tracker_ignores_flat_betting() {
+        let mut tracker = BetTracker::new();
+
+        for i in 0..30 {
+            tracker.record((i % 6) as f64 - 1.0, 25);
+        }
+
+        assert_eq!(Some(0.0), tracker.correlation());
+        assert!(!tracker.is_suspicious());
+    }
+}
@@ -1,14 +1,75 @@
-use rand::{seq::SliceRandom, thread_rng};
-use std::fmt;
+use crate::count::CardCounter;
+use crate::rng::RngSource;
+use crate::shuffle::{FisherYates, Shuffle};
+use std::{error, fmt, str::FromStr};
 
-#[derive(PartialEq, Clone)]
-enum CardSuit {
+#[derive(Debug, PartialEq)]
+pub struct ParseCardError {
+    card: String,
+}
+
+impl error::Error for ParseCardError {}
+
+impl fmt::Display for ParseCardError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Invalid card \"{}\"", self.card)
+    }
+}
+
+impl ParseCardError {
+    fn new(card: &str) -> Self {
+        Self {
+            card: card.to_string(),
+        }
+    }
+}
+
+#[derive(PartialEq, Clone, Debug)]
+pub enum CardSuit {
     Heart,
     Spade,
     Club,
     Diamond,
 }
 
+impl CardSuit {
+    pub const ALL: [CardSuit; 4] = [Self::Heart, Self::Spade, Self::Club, Self::Diamond];
+
+    /// Single letter used in the compact card notation
+    pub fn symbol(&self) -> char {
+        match self {
+            Self::Heart => 'H',
+            Self::Spade => 'S',
+            Self::Club => 'C',
+            Self::Diamond => 'D',
+        }
+    }
+
+    pub fn unicode_symbol(&self) -> char {
+        match self {
+            Self::Heart => '♥',
+            Self::Spade => '♠',
+            Self::Club => '♣',
+            Self::Diamond => '♦',
+        }
+    }
+}
+
+impl FromStr for CardSuit {
+    type Err = ParseCardError;
+
+    /// Accepts the compact letter (case insensitive) or the unicode symbol, both filled and outlined
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_uppercase().as_ref() {
+            "H" | "♥" | "♡" => Ok(Self::Heart),
+            "S" | "♠" | "♤" => Ok(Self::Spade),
+            "C" | "♣" | "♧" => Ok(Self::Club),
+            "D" | "♦" | "♢" => Ok(Self::Diamond),
+            _ => Err(ParseCardError::new(s)),
+        }
+    }
+}
+
 impl fmt::Display for CardSuit {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let text = match *self {
@@ -22,7 +83,7 @@ impl fmt::Display for CardSuit {
     }
 }
 
-#[derive(PartialEq, Clone)]
+#[derive(PartialEq, Clone, Debug)]
 pub enum CardValue {
     Ace,
     Two,
@@ -39,6 +100,71 @@ pub enum CardValue {
     King,
 }
 
+impl CardValue {
+    pub const ALL: [CardValue; 13] = [
+        Self::Ace,
+        Self::Two,
+        Self::Three,
+        Self::Four,
+        Self::Five,
+        Self::Six,
+        Self::Seven,
+        Self::Eight,
+        Self::Nine,
+        Self::Ten,
+        Self::Jack,
+        Self::Queen,
+        Self::King,
+    ];
+
+    fn index(&self) -> usize {
+        Self::ALL.iter().position(|value| value == self).unwrap()
+    }
+
+    /// Single character used in the compact card notation, ten is "T" so every card is two characters long
+    pub fn symbol(&self) -> char {
+        match self {
+            Self::Ace => 'A',
+            Self::Two => '2',
+            Self::Three => '3',
+            Self::Four => '4',
+            Self::Five => '5',
+            Self::Six => '6',
+            Self::Seven => '7',
+            Self::Eight => '8',
+            Self::Nine => '9',
+            Self::Ten => 'T',
+            Self::Jack => 'J',
+            Self::Queen => 'Q',
+            Self::King => 'K',
+        }
+    }
+}
+
+impl FromStr for CardValue {
+    type Err = ParseCardError;
+
+    /// Accepts the compact character (case insensitive), ten can also be written as "10"
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_uppercase().as_ref() {
+            "A" => Ok(Self::Ace),
+            "2" => Ok(Self::Two),
+            "3" => Ok(Self::Three),
+            "4" => Ok(Self::Four),
+            "5" => Ok(Self::Five),
+            "6" => Ok(Self::Six),
+            "7" => Ok(Self::Seven),
+            "8" => Ok(Self::Eight),
+            "9" => Ok(Self::Nine),
+            "T" | "10" => Ok(Self::Ten),
+            "J" => Ok(Self::Jack),
+            "Q" => Ok(Self::Queen),
+            "K" => Ok(Self::King),
+            _ => Err(ParseCardError::new(s)),
+        }
+    }
+}
+
 impl fmt::Display for CardValue {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let text = match self {
@@ -61,270 +187,279 @@ impl fmt::Display for CardValue {
     }
 }
 
-#[derive(PartialEq, Clone)]
+#[derive(PartialEq, Clone, Debug)]
 pub struct Card {
     suit: CardSuit,
     value: CardValue,
 }
 
+/// Two character notation of the card, e.g. "AH" for ace of hearts or "TS" for ten of spades
+/// The alternate form `{:#}` has the unicode suit symbol instead, e.g. "A♥"
 impl fmt::Display for Card {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{} of {}", self.value, self.suit)
+        if f.alternate() {
+            write!(f, "{}{}", self.value.symbol(), self.suit.unicode_symbol())
+        } else {
+            write!(f, "{}{}", self.value.symbol(), self.suit.symbol())
+        }
     }
 }
 
 impl Card {
+    pub fn new(value: CardValue, suit: CardSuit) -> Self {
+        Self { suit, value }
+    }
+
     pub fn value(&self) -> &CardValue {
         &self.value
     }
+
+    pub fn suit(&self) -> &CardSuit {
+        &self.suit
+    }
+}
+
+impl FromStr for Card {
+    type Err = ParseCardError;
+
+    /// Parses the compact notation, suit is the last character and everything before it is the value
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let trimmed = s.trim();
+        let (index, _) = trimmed.char_indices().last().ok_or_else(|| ParseCardError::new(s))?;
+        let (value, suit) = trimmed.split_at(index);
+
+        match (value.parse(), suit.parse()) {
+            (Ok(value), Ok(suit)) => Ok(Self::new(value, suit)),
+            _ => Err(ParseCardError::new(s)),
+        }
+    }
+}
+
+/// Parses whitespace separated cards in compact notation, e.g. "AH TS 9D"
+pub fn parse_cards(s: &str) -> Result<Vec<Card>, ParseCardError> {
+    s.split_whitespace().map(|card| card.parse()).collect()
+}
+
+/// Formats the cards in compact notation separated by spaces, the inverse of `parse_cards`
+pub fn format_cards(cards: &[Card]) -> String {
+    cards.iter().map(|card| card.to_string()).collect::<Vec<String>>().join(" ")
+}
+
+/// Number of cards of every value, regardless of suit
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct Composition {
+    counts: [usize; 13],
 }
 
-struct Deck {
+impl Composition {
+    pub fn count(&self, value: &CardValue) -> usize {
+        self.counts[value.index()]
+    }
+
+    pub fn total(&self) -> usize {
+        self.counts.iter().sum()
+    }
+
+    pub fn add(&mut self, value: &CardValue) {
+        self.counts[value.index()] += 1;
+    }
+
+    /// Does nothing if there are no cards of that value left
+    pub fn remove(&mut self, value: &CardValue) {
+        let count = &mut self.counts[value.index()];
+        *count = count.saturating_sub(1);
+    }
+}
+
+impl<'a> std::iter::FromIterator<&'a Card> for Composition {
+    fn from_iter<I: IntoIterator<Item = &'a Card>>(cards: I) -> Self {
+        let mut composition = Self::default();
+
+        for card in cards {
+            composition.add(card.value());
+        }
+
+        composition
+    }
+}
+
+#[derive(Clone)]
+pub struct Deck {
     cards: Vec<Card>,
 }
 
 impl Deck {
-    const SIZE: u8 = 52;
-
-    fn new() -> Deck {
-        let cards = vec![
-            Card {
-                suit: CardSuit::Heart,
-                value: CardValue::Ace,
-            },
-            Card {
-                suit: CardSuit::Heart,
-                value: CardValue::Two,
-            },
-            Card {
-                suit: CardSuit::Heart,
-                value: CardValue::Three,
-            },
-            Card {
-                suit: CardSuit::Heart,
-                value: CardValue::Four,
-            },
-            Card {
-                suit: CardSuit::Heart,
-                value: CardValue::Five,
-            },
-            Card {
-                suit: CardSuit::Heart,
-                value: CardValue::Six,
-            },
-            Card {
-                suit: CardSuit::Heart,
-                value: CardValue::Seven,
-            },
-            Card {
-                suit: CardSuit::Heart,
-                value: CardValue::Eight,
-            },
-            Card {
-                suit: CardSuit::Heart,
-                value: CardValue::Nine,
-            },
-            Card {
-                suit: CardSuit::Heart,
-                value: CardValue::Ten,
-            },
-            Card {
-                suit: CardSuit::Heart,
-                value: CardValue::Jack,
-            },
-            Card {
-                suit: CardSuit::Heart,
-                value: CardValue::Queen,
-            },
-            Card {
-                suit: CardSuit::Heart,
-                value: CardValue::King,
-            },
-            Card {
-                suit: CardSuit::Spade,
-                value: CardValue::Ace,
-            },
-            Card {
-                suit: CardSuit::Spade,
-                value: CardValue::Two,
-            },
-            Card {
-                suit: CardSuit::Spade,
-                value: CardValue::Three,
-            },
-            Card {
-                suit: CardSuit::Spade,
-                value: CardValue::Four,
-            },
-            Card {
-                suit: CardSuit::Spade,
-                value: CardValue::Five,
-            },
-            Card {
-                suit: CardSuit::Spade,
-                value: CardValue::Six,
-            },
-            Card {
-                suit: CardSuit::Spade,
-                value: CardValue::Seven,
-            },
-            Card {
-                suit: CardSuit::Spade,
-                value: CardValue::Eight,
-            },
-            Card {
-                suit: CardSuit::Spade,
-                value: CardValue::Nine,
-            },
-            Card {
-                suit: CardSuit::Spade,
-                value: CardValue::Ten,
-            },
-            Card {
-                suit: CardSuit::Spade,
-                value: CardValue::Jack,
-            },
-            Card {
-                suit: CardSuit::Spade,
-                value: CardValue::Queen,
-            },
-            Card {
-                suit: CardSuit::Spade,
-                value: CardValue::King,
-            },
-            Card {
-                suit: CardSuit::Club,
-                value: CardValue::Ace,
-            },
-            Card {
-                suit: CardSuit::Club,
-                value: CardValue::Two,
-            },
-            Card {
-                suit: CardSuit::Club,
-                value: CardValue::Three,
-            },
-            Card {
-                suit: CardSuit::Club,
-                value: CardValue::Four,
-            },
-            Card {
-                suit: CardSuit::Club,
-                value: CardValue::Five,
-            },
-            Card {
-                suit: CardSuit::Club,
-                value: CardValue::Six,
-            },
-            Card {
-                suit: CardSuit::Club,
-                value: CardValue::Seven,
-            },
-            Card {
-                suit: CardSuit::Club,
-                value: CardValue::Eight,
-            },
-            Card {
-                suit: CardSuit::Club,
-                value: CardValue::Nine,
-            },
-            Card {
-                suit: CardSuit::Club,
-                value: CardValue::Ten,
-            },
-            Card {
-                suit: CardSuit::Club,
-                value: CardValue::Jack,
-            },
-            Card {
-                suit: CardSuit::Club,
-                value: CardValue::Queen,
-            },
-            Card {
-                suit: CardSuit::Club,
-                value: CardValue::King,
-            },
-            Card {
-                suit: CardSuit::Diamond,
-                value: CardValue::Ace,
-            },
-            Card {
-                suit: CardSuit::Diamond,
-                value: CardValue::Two,
-            },
-            Card {
-                suit: CardSuit::Diamond,
-                value: CardValue::Three,
-            },
-            Card {
-                suit: CardSuit::Diamond,
-                value: CardValue::Four,
-            },
-            Card {
-                suit: CardSuit::Diamond,
-                value: CardValue::Five,
-            },
-            Card {
-                suit: CardSuit::Diamond,
-                value: CardValue::Six,
-            },
-            Card {
-                suit: CardSuit::Diamond,
-                value: CardValue::Seven,
-            },
-            Card {
-                suit: CardSuit::Diamond,
-                value: CardValue::Eight,
-            },
-            Card {
-                suit: CardSuit::Diamond,
-                value: CardValue::Nine,
-            },
-            Card {
-                suit: CardSuit::Diamond,
-                value: CardValue::Ten,
-            },
-            Card {
-                suit: CardSuit::Diamond,
-                value: CardValue::Jack,
-            },
-            Card {
-                suit: CardSuit::Diamond,
-                value: CardValue::Queen,
-            },
-            Card {
-                suit: CardSuit::Diamond,
-                value: CardValue::King,
-            },
-        ];
+    /// Number of cards in a standard deck
+    pub const SIZE: u8 = 52;
+
+    /// Standard 52 card deck
+    pub fn new() -> Deck {
+        Self::builder().build()
+    }
+
+    /// Spanish deck used in Spanish 21, a standard deck with all the tens (but not the face cards) removed
+    pub fn spanish() -> Deck {
+        Self::builder().without(CardValue::Ten).build()
+    }
+
+    pub fn builder() -> DeckBuilder {
+        DeckBuilder::default()
+    }
+
+    pub fn cards(&self) -> &[Card] {
+        &self.cards
+    }
+}
+
+impl Default for Deck {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Generates a deck from a set of suits and values, defaults to a standard 52 card deck
+pub struct DeckBuilder {
+    suits: Vec<CardSuit>,
+    values: Vec<CardValue>,
+    extra: Vec<Card>,
+}
+
+impl Default for DeckBuilder {
+    fn default() -> Self {
+        Self {
+            suits: CardSuit::ALL.to_vec(),
+            values: CardValue::ALL.to_vec(),
+            extra: Vec::new(),
+        }
+    }
+}
+
+impl DeckBuilder {
+    /// Replaces the suits the deck is generated from
+    pub fn suits(mut self, suits: &[CardSuit]) -> Self {
+        self.suits = suits.to_vec();
+        self
+    }
+
+    /// Replaces the values the deck is generated from
+    pub fn values(mut self, values: &[CardValue]) -> Self {
+        self.values = values.to_vec();
+        self
+    }
+
+    /// Strips the value from every suit of the deck
+    pub fn without(mut self, value: CardValue) -> Self {
+        self.values.retain(|v| *v != value);
+        self
+    }
+
+    /// Adds copies of the card on top of the generated suit/value combinations
+    pub fn extra(mut self, card: Card, copies: u8) -> Self {
+        for _ in 0..copies {
+            self.extra.push(card.clone());
+        }
+        self
+    }
+
+    pub fn build(&self) -> Deck {
+        let mut cards = Vec::with_capacity(self.suits.len() * self.values.len() + self.extra.len());
+
+        for suit in self.suits.iter() {
+            for value in self.values.iter() {
+                cards.push(Card::new(value.clone(), suit.clone()));
+            }
+        }
+
+        cards.extend(self.extra.iter().cloned());
 
         Deck { cards }
     }
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum ShoeError {
+    /// Shoe was created without any decks in it
+    InvalidDeckCount,
+    /// Shoe was created with a deck that has no cards in it
+    EmptyDeck,
+    /// Tried to pull a card from a shoe that has no cards left
+    Empty,
+}
+
+impl fmt::Display for ShoeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let text = match self {
+            Self::InvalidDeckCount => "Deck count has to be at least 1",
+            Self::EmptyDeck => "Deck has to contain at least 1 card",
+            Self::Empty => "Tried to pull card from empty shoe",
+        };
+
+        write!(f, "{}", text)
+    }
+}
+
+impl error::Error for ShoeError {}
 
-    fn cards(&mut self) -> &mut Vec<Card> {
-        &mut self.cards
+impl From<ShoeError> for network::error::Error {
+    fn from(error: ShoeError) -> Self {
+        match error {
+            ShoeError::Empty => Self::ShoeEmpty,
+            // Games are only dealt from shoes that were created successfully
+            ShoeError::InvalidDeckCount | ShoeError::EmptyDeck => Self::ServerError,
+        }
     }
 }
 
 pub struct Shoe {
+    /// Deck the shoe gets refilled with. Stacked shoes don't have one since they never refill
+    deck: Option<Deck>,
     deck_count: u8,
     /// Minimum number of cards allowed before shoe refills itself
-    minimum_cards: u16, // With maximum 255 decks, the max number this could ever hold is 3978, so 16 bits is enough
+    minimum_cards: usize,
     cards: Vec<Card>,
+    shuffle: Box<dyn Shuffle>,
+    rng: RngSource,
+    counter: CardCounter,
+    /// Times the shoe has been refilled with freshly shuffled cards
+    shuffles: u32,
 }
 
 impl Shoe {
-    pub fn new(deck_count: u8) -> Result<Self, &'static str> {
+    /// Shoe of standard 52 card decks
+    pub fn new(deck_count: u8) -> Result<Self, ShoeError> {
+        Self::new_with_rng(deck_count, RngSource::Thread)
+    }
+
+    /// Shoe of standard 52 card decks shuffled with the source of randomness from the start
+    pub fn new_with_rng(deck_count: u8, rng: RngSource) -> Result<Self, ShoeError> {
+        Self::filled(Deck::new(), deck_count, rng)
+    }
+
+    pub fn with_deck(deck: Deck, deck_count: u8) -> Result<Self, ShoeError> {
+        Self::filled(deck, deck_count, RngSource::Thread)
+    }
+
+    fn filled(deck: Deck, deck_count: u8, rng: RngSource) -> Result<Self, ShoeError> {
         if deck_count < 1 {
-            return Err("Deck count has to be at least 1");
+            return Err(ShoeError::InvalidDeckCount);
+        }
+
+        if deck.cards.is_empty() {
+            return Err(ShoeError::EmptyDeck);
         }
 
-        let minimum_cards = Deck::SIZE as u32 * deck_count as u32 * 30 / 100;
+        let total_cards = deck.cards.len() * deck_count as usize;
+        let counter = CardCounter::new(deck.cards.len());
 
         let mut shoe = Self {
+            deck: Some(deck),
             deck_count,
-            minimum_cards: minimum_cards as u16,
-            cards: Vec::with_capacity((deck_count as u16 * Deck::SIZE as u16) as usize),
+            minimum_cards: total_cards * 30 / 100,
+            cards: Vec::with_capacity(total_cards),
+            shuffle: Box::new(FisherYates),
+            rng,
+            counter,
+            shuffles: 0,
         };
 
         shoe.refill();
@@ -332,30 +467,94 @@ impl Shoe {
         Ok(shoe)
     }
 
-    pub fn pull_card(&mut self) -> Card{
-        let card = match self.cards.pop() {
-            Some(card) => card,
-            None => panic!("Tried to pull card from empty shoe")
-        };
+    /// Shoe that deals the given cards in order and never refills, once all the cards are dealt pulling returns `ShoeError::Empty`
+    pub fn stacked(cards: Vec<Card>) -> Self {
+        let mut cards = cards;
+        // Cards get pulled from the end of the vector
+        cards.reverse();
+
+        let mut counter = CardCounter::new(Deck::SIZE as usize);
+        counter.reset(cards.len());
+
+        Self {
+            deck: None,
+            deck_count: 0,
+            minimum_cards: 0,
+            cards,
+            shuffle: Box::new(FisherYates),
+            rng: RngSource::Thread,
+            counter,
+            shuffles: 0,
+        }
+    }
+
+    /// Replaces the shuffling strategy and refills the shoe with cards shuffled by it
+    pub fn with_shuffle(mut self, shuffle: impl Shuffle + 'static) -> Self {
+        self.shuffle = Box::new(shuffle);
+        self.refill();
+        self
+    }
+
+    /// Replaces the source of randomness and refills the shoe with cards shuffled using it
+    pub fn with_rng(mut self, rng: RngSource) -> Self {
+        self.rng = rng;
+        self.refill();
+        self
+    }
+
+    /// Takes the cards out of the shoe as if they were dealt from it, for continuing a round dealt from another shoe
+    /// Cards that aren't in the shoe, e.g. one that was stacked, are skipped
+    pub fn take_out(&mut self, cards: &[Card]) {
+        for card in cards {
+            if let Some(index) = self.cards.iter().position(|in_shoe| in_shoe == card) {
+                self.cards.remove(index);
+                self.counter.card_dealt(card);
+            }
+        }
+    }
 
-        if (self.cards.len() as u16) < self.minimum_cards {
+    pub fn pull_card(&mut self) -> Result<Card, ShoeError> {
+        let card = self.cards.pop().ok_or(ShoeError::Empty)?;
+        self.counter.card_dealt(&card);
+
+        if self.cards.len() < self.minimum_cards {
             self.refill();
         }
 
-        card
+        Ok(card)
     }
 
     /// Empties the shoe and refills it with new decks
     fn refill(&mut self) {
+        let deck = match &self.deck {
+            Some(deck) => deck,
+            None => return,
+        };
+
         self.cards.clear();
 
         for _ in 0..self.deck_count {
-            let mut deck = Deck::new();
-            self.cards.append(&mut deck.cards());
+            self.cards.extend(deck.cards.iter().cloned());
         }
 
-        let mut rng = thread_rng();
-        self.cards.shuffle(&mut rng);
+        self.shuffle.shuffle(&mut self.cards, self.rng.rng().as_mut());
+        self.counter.reset(self.cards.len());
+        self.shuffles += 1;
+    }
+
+    /// Times the shoe has been shuffled, comparing two values tells if it was reshuffled in between
+    pub fn shuffles(&self) -> u32 {
+        self.shuffles
+    }
+
+    /// Number of cards of every value left in the shoe
+    pub fn composition(&self) -> Composition {
+        self.cards.iter().collect()
+    }
+
+    /// Card counts of the cards dealt since the last refill
+    pub fn counter(&self) -> &CardCounter {
+        &self.counter
     }
 }
 
@@ -368,7 +567,7 @@ mod tests {
         use super::*;
 
         impl Deck {
-            pub fn len(&self) -> usize {
+            fn len(&self) -> usize {
                 self.cards.len()
             }
 
@@ -618,14 +817,97 @@ mod tests {
                 assert!(deck.contains(card));
             }
         }
+
+        #[test]
+        fn spanish_deck_has_no_tens() {
+            let deck = Deck::spanish();
+            assert_eq!(48, deck.len());
+
+            for suit in CardSuit::ALL.iter() {
+                assert!(!deck.contains(&Card::new(CardValue::Ten, suit.clone())));
+                assert!(deck.contains(&Card::new(CardValue::King, suit.clone())));
+            }
+        }
+
+        #[test]
+        fn builder_uses_given_suits_and_values() {
+            let deck = Deck::builder()
+                .suits(&[CardSuit::Spade])
+                .values(&[CardValue::Ace, CardValue::King])
+                .build();
+
+            assert_eq!(2, deck.len());
+            assert!(deck.contains(&Card::new(CardValue::Ace, CardSuit::Spade)));
+            assert!(deck.contains(&Card::new(CardValue::King, CardSuit::Spade)));
+        }
+
+        #[test]
+        fn builder_adds_extra_cards() {
+            let ace = Card::new(CardValue::Ace, CardSuit::Heart);
+            let deck = Deck::builder().extra(ace.clone(), 3).build();
+
+            assert_eq!(55, deck.len());
+            assert_eq!(4, deck.cards().iter().filter(|card| **card == ace).count());
+        }
+    }
+
+    mod notation {
+        use super::*;
+
+        #[test]
+        fn compact_format() {
+            assert_eq!("AH", Card::new(CardValue::Ace, CardSuit::Heart).to_string());
+            assert_eq!("TS", Card::new(CardValue::Ten, CardSuit::Spade).to_string());
+            assert_eq!("9D", Card::new(CardValue::Nine, CardSuit::Diamond).to_string());
+            assert_eq!("KC", Card::new(CardValue::King, CardSuit::Club).to_string());
+            assert_eq!("Q♥", format!("{:#}", Card::new(CardValue::Queen, CardSuit::Heart)));
+        }
+
+        #[test]
+        fn parse_valid_cards() {
+            assert_eq!(Ok(Card::new(CardValue::Ace, CardSuit::Heart)), "AH".parse());
+            assert_eq!(Ok(Card::new(CardValue::Ten, CardSuit::Spade)), "ts".parse());
+            assert_eq!(Ok(Card::new(CardValue::Ten, CardSuit::Spade)), "10S".parse());
+            assert_eq!(Ok(Card::new(CardValue::Nine, CardSuit::Diamond)), " 9d ".parse());
+            assert_eq!(Ok(Card::new(CardValue::Jack, CardSuit::Club)), "J♣".parse());
+            assert_eq!(Ok(Card::new(CardValue::Two, CardSuit::Heart)), "2♡".parse());
+        }
+
+        #[test]
+        fn parse_invalid_cards() {
+            assert!("".parse::<Card>().is_err());
+            assert!("A".parse::<Card>().is_err());
+            assert!("1H".parse::<Card>().is_err());
+            assert!("AX".parse::<Card>().is_err());
+            assert!("AHS".parse::<Card>().is_err());
+        }
+
+        #[test]
+        fn every_card_roundtrips() {
+            let deck = Deck::new();
+
+            for card in deck.cards() {
+                assert_eq!(Ok(card.clone()), card.to_string().parse());
+                assert_eq!(Ok(card.clone()), format!("{:#}", card).parse());
+            }
+        }
+
+        #[test]
+        fn card_lists() {
+            let cards = parse_cards("AH TS  9D").unwrap();
+            assert_eq!(3, cards.len());
+            assert_eq!("AH TS 9D", format_cards(&cards));
+            assert!(parse_cards("AH XX").is_err());
+        }
     }
 
     mod shoe {
         use super::*;
+        use crate::count::CountingSystem;
 
         #[test]
         fn invalid_deck_count() {
-            assert!(Shoe::new(0).is_err());
+            assert_eq!(Shoe::new(0).err(), Some(ShoeError::InvalidDeckCount));
         }
 
         #[test]
@@ -658,13 +940,117 @@ mod tests {
         fn refill() {
             let mut shoe = create_shoe(3);
 
-            while (shoe.cards.len() as u16) > shoe.minimum_cards {
-                shoe.pull_card();
+            while shoe.cards.len() > shoe.minimum_cards {
+                assert!(shoe.pull_card().is_ok());
+            }
+
+            assert_eq!(shoe.cards.len(), shoe.minimum_cards);
+            let shuffles = shoe.shuffles();
+            assert!(shoe.pull_card().is_ok());
+            assert_eq!(shoe.cards.len(), shoe.deck_count as usize * Deck::SIZE as usize);
+            assert_eq!(shuffles + 1, shoe.shuffles());
+        }
+
+        #[test]
+        fn pull_from_empty_shoe() {
+            let mut shoe = create_shoe(1);
+            shoe.cards.clear();
+
+            assert_eq!(shoe.pull_card().err(), Some(ShoeError::Empty));
+        }
+
+        #[test]
+        fn empty_deck() {
+            let deck = Deck::builder().values(&[]).build();
+            assert_eq!(Shoe::with_deck(deck, 1).err(), Some(ShoeError::EmptyDeck));
+        }
+
+        #[test]
+        fn custom_deck_sizes() {
+            let shoe = Shoe::with_deck(Deck::spanish(), 6).unwrap();
+            assert_eq!(shoe.cards.len(), 288);
+            assert_eq!(shoe.minimum_cards, 86);
+        }
+
+        #[test]
+        fn custom_shuffle() {
+            let shoe = create_shoe(2).with_shuffle(crate::shuffle::Procedure::casino());
+            assert_eq!(shoe.cards.len(), 104);
+        }
+
+        #[test]
+        fn secure_rng() {
+            let shoe = create_shoe(1).with_rng(RngSource::Os);
+            assert_eq!(shoe.rng, RngSource::Os);
+            assert_eq!(shoe.cards.len(), 52);
+        }
+
+        #[test]
+        fn shuffled_once_with_rng() {
+            let shoe = Shoe::new_with_rng(1, RngSource::Os).unwrap();
+            assert_eq!(shoe.rng, RngSource::Os);
+            assert_eq!(1, shoe.shuffles());
+        }
+
+        #[test]
+        fn counter_follows_dealt_cards() {
+            let mut shoe = Shoe::stacked(parse_cards("2H 3S KD 5C").unwrap());
+            assert_eq!(0, shoe.counter().count(CountingSystem::HiLo).running);
+
+            for _ in 0..4 {
+                assert!(shoe.pull_card().is_ok());
+            }
+
+            assert_eq!(2, shoe.counter().count(CountingSystem::HiLo).running);
+        }
+
+        #[test]
+        fn counter_resets_on_refill() {
+            let mut shoe = create_shoe(1);
+
+            while shoe.cards.len() > shoe.minimum_cards {
+                assert!(shoe.pull_card().is_ok());
+            }
+
+            // Takes the shoe below the minimum and triggers the refill
+            assert!(shoe.pull_card().is_ok());
+            assert_eq!(shoe.cards.len(), 52);
+
+            let count = shoe.counter().count(CountingSystem::HiLo);
+            assert_eq!(0, count.running);
+            assert_eq!(0.0, count.true_count);
+        }
+
+        #[test]
+        fn composition_of_remaining_cards() {
+            let mut shoe = create_shoe(2);
+            let composition = shoe.composition();
+            assert_eq!(104, composition.total());
+
+            for value in CardValue::ALL.iter() {
+                assert_eq!(8, composition.count(value));
+            }
+
+            let card = shoe.pull_card().unwrap();
+            let composition = shoe.composition();
+            assert_eq!(103, composition.total());
+            assert_eq!(7, composition.count(card.value()));
+        }
+
+        #[test]
+        fn stacked_shoe_deals_in_order() {
+            let cards = vec![
+                Card::new(CardValue::Ace, CardSuit::Spade),
+                Card::new(CardValue::Ten, CardSuit::Heart),
+                Card::new(CardValue::Five, CardSuit::Club),
+            ];
+            let mut shoe = Shoe::stacked(cards.clone());
+
+            for card in cards {
+                assert_eq!(shoe.pull_card(), Ok(card));
             }
 
-            assert_eq!(shoe.cards.len() as u16, shoe.minimum_cards);
-            shoe.pull_card();
-            assert_eq!(shoe.cards.len() as u16, shoe.deck_count as u16 * Deck::SIZE as u16);
+            assert_eq!(shoe.pull_card(), Err(ShoeError::Empty));
         }
     }
 }
@@ -0,0 +1,13 @@
+pub mod account;
+pub mod auth;
+pub mod blackjack;
+pub mod count;
+pub mod deck;
+pub mod odds;
+pub mod rng;
+pub mod server;
+pub mod shuffle;
+pub mod storage;
+pub mod transport;
+
+pub use crate::server::Server;
@@ -1,9 +1,112 @@
-mod blackjack;
-mod deck;
-mod server;
+use network::alias::Aliases;
+use server::rng::RngSource;
+use server::auth::Credential;
+use server::storage::{FileStorage, Storage};
+use server::Server;
+use std::env;
+use std::io;
+
+/// Value following the flag, e.g. the path in "--unix /tmp/blackjack.sock"
+fn argument<'a>(args: &'a [String], flag: &str) -> Option<&'a String> {
+    args.iter().position(|arg| arg == flag).and_then(|index| args.get(index + 1))
+}
+
+/// Gives the account the password read from stdin
+/// Accounts saved before there were passwords can't be logged in to or registered again until they get one this way
+fn set_password(storage: &mut impl Storage, name: &str) -> Result<(), String> {
+    let name = name.to_lowercase();
+    if storage.load(&name).is_none() && storage.credential(&name).is_none() {
+        return Err(format!("There's no account called {}", name));
+    }
+
+    let mut password = String::new();
+    io::stdin().read_line(&mut password).map_err(|e| format!("Unable to read the password: {}", e))?;
+    let credential = Credential::new(password.trim_end_matches(['\r', '\n'])).map_err(|e| e.to_string())?;
+    storage.set_credential(&name, &credential).map_err(|e| format!("Unable to save the password: {}", e))
+}
 
 fn main() {
     env_logger::init();
-    let mut server = server::Server::new("localhost:2024");
+    let args: Vec<String> = env::args().collect();
+    let mut server = Server::new("localhost:2024");
+
+    // Real money tables should always be started with --secure-rng
+    if args.iter().any(|arg| arg == "--secure-rng") {
+        server.set_rng(RngSource::Os);
+    }
+
+    // Local clients and bots can skip TCP with --unix <path>
+    #[cfg(unix)]
+    {
+        if let Some(path) = argument(&args, "--unix") {
+            server.set_unix_socket(path);
+        }
+    }
+
+    // Bigger messages are refused with --max-frame-size <bytes>
+    if let Some(size) = argument(&args, "--max-frame-size") {
+        match size.parse() {
+            Ok(size) => server.set_max_frame_size(size),
+            Err(e) => {
+                eprintln!("Invalid --max-frame-size \"{}\": {}", size, e);
+                return;
+            },
+        }
+    }
+
+    // Browser clients connect with --websocket <address>, e.g. "localhost:2025"
+    if let Some(address) = argument(&args, "--websocket") {
+        server.set_websocket_address(address.as_str());
+    }
+
+    // Encrypted TCP and WebSocket connections with --tls-cert <path> --tls-key <path>, both PEM files
+    match (argument(&args, "--tls-cert"), argument(&args, "--tls-key")) {
+        (Some(certificate), Some(key)) => server.set_tls(certificate, key),
+        (None, None) => (),
+        _ => {
+            eprintln!("TLS needs both --tls-cert and --tls-key");
+            return;
+        },
+    }
+
+    // Balances survive restarts with --accounts <path>, a JSON file that is created if it doesn't exist
+    // Adding --set-password <name> sets the account's password to the line on stdin and exits instead of starting the server
+    let set_password_of = argument(&args, "--set-password");
+    if let Some(path) = argument(&args, "--accounts") {
+        match FileStorage::open(path) {
+            Ok(mut storage) => match set_password_of {
+                Some(name) => {
+                    match set_password(&mut storage, name) {
+                        Ok(_) => println!("Password of {} is set", name),
+                        Err(e) => eprintln!("{}", e),
+                    }
+                    return;
+                },
+                None => server.set_storage(storage),
+            },
+            Err(e) => {
+                eprintln!("Unable to read the accounts from {}: {}", path, e);
+                return;
+            },
+        }
+    }
+
+    if set_password_of.is_some() {
+        eprintln!("--set-password needs --accounts");
+        return;
+    }
+
+    // More shorthands for text clients with --alias <alias>=<command>, can be given many times
+    let mut aliases = Aliases::default();
+    for (flag, definition) in args.iter().zip(args.iter().skip(1)) {
+        if flag == "--alias" {
+            if let Err(e) = aliases.define(definition) {
+                eprintln!("{}: {}", e, e.error());
+                return;
+            }
+        }
+    }
+    server.set_aliases(aliases);
+
     server.start().unwrap();
 }
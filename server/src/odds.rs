@@ -0,0 +1,146 @@
+use crate::deck::{CardValue, Composition};
+use std::fmt;
+
+/// Probabilities of the totals the dealer can finish a round with
+#[derive(Debug, PartialEq, Clone)]
+pub struct DealerOdds {
+    /// Indexed by the final total
+    totals: [f64; 22],
+    bust: f64,
+}
+
+impl DealerOdds {
+    pub fn probability(&self, total: u8) -> f64 {
+        self.totals.get(total as usize).copied().unwrap_or(0.0)
+    }
+
+    pub fn bust(&self) -> f64 {
+        self.bust
+    }
+
+    /// Totals the dealer can finish with and their probabilities, from lowest to highest
+    pub fn totals(&self) -> impl Iterator<Item = (u8, f64)> + '_ {
+        self.totals
+            .iter()
+            .enumerate()
+            .filter(|(_, probability)| **probability > 0.0)
+            .map(|(total, probability)| (total as u8, *probability))
+    }
+}
+
+impl fmt::Display for DealerOdds {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (total, probability) in self.totals() {
+            write!(f, "{}: {:.1}%\r\n", total, probability * 100.0)?;
+        }
+
+        write!(f, "Bust: {:.1}%", self.bust * 100.0)
+    }
+}
+
+/// Cards grouped by the points they are worth, tens and face cards are the same for the dealer
+fn point_counts(composition: &Composition) -> [usize; 10] {
+    let mut counts = [0; 10];
+
+    for value in CardValue::ALL.iter() {
+        counts[value.value() as usize - 1] += composition.count(value);
+    }
+
+    counts
+}
+
+/// Exact odds of the dealer's final total when the hole card and every following card are drawn from the given
+/// cards. Dealer draws until the total reaches `stand_on`, aces count as 1 just like in the game
+pub fn dealer_odds(composition: &Composition, upcard: &CardValue, stand_on: u8) -> DealerOdds {
+    let mut odds = DealerOdds {
+        totals: [0.0; 22],
+        bust: 0.0,
+    };
+
+    let mut counts = point_counts(composition);
+    let remaining = composition.total();
+    draw(&mut odds, &mut counts, remaining, upcard.value(), 1, stand_on, 1.0);
+
+    odds
+}
+
+fn draw(
+    odds: &mut DealerOdds,
+    counts: &mut [usize; 10],
+    remaining: usize,
+    total: u8,
+    cards: u8,
+    stand_on: u8,
+    probability: f64,
+) {
+    if total > 21 {
+        odds.bust += probability;
+        return;
+    }
+
+    // Dealer always has the hole card, and has to stop when there's nothing left to draw
+    if (cards >= 2 && total >= stand_on) || remaining == 0 {
+        odds.totals[total as usize] += probability;
+        return;
+    }
+
+    for points in 1..=10u8 {
+        let count = counts[points as usize - 1];
+        if count == 0 {
+            continue;
+        }
+
+        let next = probability * count as f64 / remaining as f64;
+        counts[points as usize - 1] -= 1;
+        draw(odds, counts, remaining - 1, total + points, cards + 1, stand_on, next);
+        counts[points as usize - 1] += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::deck::{self, Deck, Shoe};
+
+    fn composition(cards: &str) -> Composition {
+        deck::parse_cards(cards).unwrap().iter().collect()
+    }
+
+    #[test]
+    fn probabilities_sum_to_one() {
+        let shoe = Shoe::with_deck(Deck::new(), 6).unwrap();
+
+        for upcard in CardValue::ALL.iter() {
+            let odds = dealer_odds(&shoe.composition(), upcard, 17);
+            let sum: f64 = odds.totals().map(|(_, probability)| probability).sum::<f64>() + odds.bust();
+            assert!((sum - 1.0).abs() < 1e-9, "{}", upcard);
+            assert_eq!(0.0, odds.probability(16));
+        }
+    }
+
+    #[test]
+    fn only_one_outcome() {
+        // Six with a ten in the hole stands on 16
+        let odds = dealer_odds(&composition("TH KS QD"), &CardValue::Six, 16);
+        assert_eq!(1.0, odds.probability(16));
+        assert_eq!(0.0, odds.bust());
+
+        // Sixteen has to draw another ten when standing on 17
+        let odds = dealer_odds(&composition("TH KS QD"), &CardValue::Six, 17);
+        assert_eq!(1.0, odds.bust());
+    }
+
+    #[test]
+    fn removal_is_exact() {
+        // Hole card is the five or the ten, five leaves only the ten to draw
+        let odds = dealer_odds(&composition("5H TS"), &CardValue::Ten, 17);
+        assert_eq!(0.5, odds.probability(20));
+        assert_eq!(0.5, odds.bust());
+    }
+
+    #[test]
+    fn stops_when_cards_run_out() {
+        let odds = dealer_odds(&composition("2H"), &CardValue::Two, 17);
+        assert_eq!(1.0, odds.probability(4));
+    }
+}
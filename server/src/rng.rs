@@ -0,0 +1,140 @@
+use crate::deck::Deck;
+use crate::shuffle::Shuffle;
+use rand::{rngs::OsRng, thread_rng, RngCore};
+use std::{error, fmt};
+
+/// Where the shoe gets its randomness from
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum RngSource {
+    /// Fast userspace generator seeded from the OS
+    Thread,
+    /// Operating system's cryptographically secure generator, meant for real money games
+    Os,
+}
+
+impl RngSource {
+    pub fn rng(self) -> Box<dyn RngCore> {
+        match self {
+            Self::Thread => Box::new(thread_rng()),
+            Self::Os => Box::new(OsRng),
+        }
+    }
+}
+
+impl fmt::Display for RngSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let text = match self {
+            Self::Thread => "thread",
+            Self::Os => "os",
+        };
+
+        write!(f, "{}", text)
+    }
+}
+
+#[derive(Debug)]
+pub struct SelfTestError {
+    statistic: f64,
+    bounds: (f64, f64),
+}
+
+impl error::Error for SelfTestError {}
+
+impl fmt::Display for SelfTestError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Shuffle self-test failed, chi-square statistic {:.1} is outside of the accepted range {:.1}..{:.1}",
+            self.statistic, self.bounds.0, self.bounds.1
+        )
+    }
+}
+
+/// Number of shuffles the self-test does, gives an expected count of 50 for every card and position pair
+const SELF_TEST_ROUNDS: u32 = Deck::SIZE as u32 * 50;
+/// Standard normal quantile for the two sided test, a correct generator fails about once in 5000 startups
+const SELF_TEST_Z: f64 = 3.719;
+
+/// Chi-square statistic of how often every card of a deck lands in every position over the given number of shuffles
+pub fn chi_square(shuffle: &dyn Shuffle, rng: &mut dyn RngCore, rounds: u32) -> f64 {
+    let deck = Deck::new();
+    let size = deck.cards().len();
+    let mut counts = vec![0u32; size * size];
+
+    for _ in 0..rounds {
+        let mut cards = deck.cards().to_vec();
+        shuffle.shuffle(&mut cards, rng);
+
+        for (position, card) in cards.iter().enumerate() {
+            let index = deck.cards().iter().position(|c| c == card).unwrap();
+            counts[index * size + position] += 1;
+        }
+    }
+
+    let expected = rounds as f64 / size as f64;
+    counts
+        .iter()
+        .map(|count| (*count as f64 - expected).powi(2) / expected)
+        .sum()
+}
+
+/// Critical values of the chi-square distribution using the Wilson–Hilferty approximation
+fn bounds(degrees_of_freedom: f64, z: f64) -> (f64, f64) {
+    let variance = 2.0 / (9.0 * degrees_of_freedom);
+    let value = |z: f64| degrees_of_freedom * (1.0 - variance + z * variance.sqrt()).powi(3);
+
+    (value(-z), value(z))
+}
+
+fn check(statistic: f64, bounds: (f64, f64)) -> Result<f64, SelfTestError> {
+    if statistic < bounds.0 || statistic > bounds.1 {
+        return Err(SelfTestError { statistic, bounds });
+    }
+
+    Ok(statistic)
+}
+
+/// Checks that shuffling with the given strategy and randomness source distributes every card uniformly over
+/// every position. Too good of a fit fails as well since that is just as unlikely from a real random source
+pub fn self_test(shuffle: &dyn Shuffle, source: RngSource) -> Result<f64, SelfTestError> {
+    let size = Deck::SIZE as f64;
+    let statistic = chi_square(shuffle, source.rng().as_mut(), SELF_TEST_ROUNDS);
+
+    check(statistic, bounds((size - 1.0).powi(2), SELF_TEST_Z))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shuffle::{FisherYates, Riffle};
+    use rand::{rngs::StdRng, SeedableRng};
+
+    #[test]
+    fn bounds_match_chi_square_table() {
+        // Table values for 100 degrees of freedom at 0.1% and 99.9%
+        let (lower, upper) = bounds(100.0, 3.090);
+        assert!((lower - 61.92).abs() < 0.5);
+        assert!((upper - 149.45).abs() < 0.5);
+    }
+
+    #[test]
+    fn uniform_shuffle_passes() {
+        let mut rng = StdRng::seed_from_u64(2024);
+        let statistic = chi_square(&FisherYates, &mut rng, SELF_TEST_ROUNDS);
+
+        assert!(check(statistic, bounds(51.0 * 51.0, SELF_TEST_Z)).is_ok());
+    }
+
+    #[test]
+    fn single_riffle_fails() {
+        let mut rng = StdRng::seed_from_u64(2024);
+        let statistic = chi_square(&Riffle::new(1), &mut rng, SELF_TEST_ROUNDS);
+
+        assert!(check(statistic, bounds(51.0 * 51.0, SELF_TEST_Z)).is_err());
+    }
+
+    #[test]
+    fn too_perfect_fit_fails() {
+        assert!(check(0.0, bounds(51.0 * 51.0, SELF_TEST_Z)).is_err());
+    }
+}
@@ -2,46 +2,275 @@ use log::*;
 use std::collections::HashMap;
 use std::fmt::Display;
 use std::io;
-use std::net::{Shutdown, TcpListener, TcpStream, ToSocketAddrs};
+use std::iter;
+use std::net::{TcpListener, ToSocketAddrs};
+#[cfg(unix)]
+use std::os::unix::net::UnixListener;
+#[cfg(unix)]
+use std::path::PathBuf;
 use std::sync::{mpsc, Arc, Mutex};
 use std::thread;
+use std::time::{Duration, Instant};
 
+use crate::auth::{Credential, Lockouts, Sessions};
 use crate::blackjack;
-use crate::blackjack::Blackjack;
-use network;
+use crate::blackjack::{Blackjack, IdlePolicy};
+use crate::count::CountingSystem;
+use crate::deck::{Card, Shoe};
+use crate::rng::{self, RngSource};
+use crate::shuffle::FisherYates;
+use crate::account::Account;
+use crate::storage::{MemoryStorage, Storage};
+use crate::transport::{StreamTransport, Transport, WebSocketTransport};
+use network::alias::Aliases;
+use network::catalog::{self, Language};
+use network::command::Command;
+use network::codec::TextCodec;
+use network::error::Error;
+use network::server_message::{CommandHelp, Hand, Outcome, SystemCount, TotalProbability};
+use network::handshake::{Capability, Handshake};
+use network::tls::{self, TlsStream};
+use network::{Protocol, Request, Response, ServerMessage};
 
 type BlackjackWrapper = Arc<Mutex<Blackjack>>;
 
+/// How often a connection waiting for its client sends the events other connections left for it
+const EVENT_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// What the server leaves for a connection, delivered while it waits for its client
+enum Pushed {
+    /// Change another connection made to the game
+    Event(ServerMessage),
+    /// Password of the account was changed on another connection, the client has to log in again
+    LogOut,
+}
+
 struct Connection {
-    stream: TcpStream,
-    buffer: io::BufReader<TcpStream>,
+    transport: Box<dyn Transport>,
     blackjack: Option<BlackjackWrapper>,
+    /// Name the connection is logged in with
+    account: Option<String>,
+    /// Token for resuming the session after the connection is gone
+    token: Option<String>,
+    protocol: Protocol,
+    max_frame_size: u64,
+    /// Id of the request that is being answered
+    request_id: Option<u32>,
+    language: Language,
+    aliases: Aliases,
+    idle_timeout: Option<Duration>,
+    /// Changes other connections made to the game, sent while waiting for the client
+    events: mpsc::Receiver<Pushed>,
+    /// Last reply, passed on to the other connections of the account if it tells about a change to the game
+    reply: Option<ServerMessage>,
+    /// Responses to the command being run, held back until the changes it made are saved
+    held: Option<Vec<Response>>,
 }
 
 impl Connection {
-    fn new(stream: TcpStream) -> Connection {
-        let clone = stream.try_clone().unwrap();
+    fn new(
+        mut transport: Box<dyn Transport>,
+        max_frame_size: u64,
+        idle_timeout: Option<Duration>,
+        aliases: Aliases,
+        events: mpsc::Receiver<Pushed>,
+    ) -> Connection {
+        // Without a timeout a client that vanished without closing the connection would never be noticed
+        if let Err(e) = transport.set_read_timeout(idle_timeout) {
+            warn!("Unable to set read timeout for {}: {}", transport.peer(), e);
+        }
+
         Connection {
-            stream,
-            buffer: io::BufReader::new(clone),
+            transport,
             blackjack: None,
+            account: None,
+            token: None,
+            protocol: Protocol::Text,
+            max_frame_size,
+            request_id: None,
+            language: Language::default(),
+            aliases,
+            idle_timeout,
+            events,
+            reply: None,
+            held: None,
         }
     }
 
     fn send(&mut self, message: impl Display) {
-        network::send(&self.stream, message).unwrap();
+        if let Err(e) = self.transport.write_frame(&TextCodec, message.to_string().as_bytes()) {
+            warn!("Sending to {} failed: {}", self.transport.peer(), e);
+        }
+    }
+
+    /// Sends the message in the format of the connection's protocol as a reply to the current request
+    fn send_message(&mut self, message: ServerMessage) {
+        self.reply = Some(message.clone());
+        let response = Response::reply(self.request_id, message);
+        self.send_response(response);
+    }
+
+    /// Sends a message the client didn't ask for
+    fn send_event(&mut self, message: ServerMessage) {
+        self.send_response(Response::event(message));
+    }
+
+    /// Every response tells what the player can do next in their round
+    fn send_response(&mut self, response: Response) {
+        let actions = match &self.blackjack {
+            Some(blackjack) => blackjack.lock().unwrap().legal_actions(),
+            None => Vec::new(),
+        };
+        let response = response.with_actions(actions);
+
+        match &mut self.held {
+            Some(held) => held.push(response),
+            None => self.write_response(response),
+        }
+    }
+
+    fn write_response(&mut self, response: Response) {
+        if let Err(e) = self.transport.write_frame(self.protocol.codec(), &response.encode_in(self.protocol, self.language)) {
+            warn!("Sending to {} failed: {}", self.transport.peer(), e);
+        }
+    }
+
+    /// Ends the ongoing round of the game the client left behind, the result is for the account's other connections
+    fn abandon_round(&self, policy: IdlePolicy) -> Option<ServerMessage> {
+        let mut blackjack = self.blackjack.as_ref()?.lock().unwrap();
+
+        match blackjack.abandon(policy) {
+            Ok(Some(winner)) => {
+                info!("Abandoned round of {} ended: {}", self.transport.peer(), winner);
+                Some(round_result(&blackjack, outcome(&blackjack, winner)))
+            },
+            Ok(None) => None,
+            Err(e) => {
+                warn!("Unable to end the abandoned round of {}: {}", self.transport.peer(), e);
+                None
+            }
+        }
+    }
+
+    /// Game the connection is logged in to and the times its shoe has been shuffled
+    fn shoe_state(&self) -> Option<(BlackjackWrapper, u32)> {
+        self.blackjack
+            .as_ref()
+            .map(|blackjack| (blackjack.clone(), blackjack.lock().unwrap().shuffles()))
     }
 
-    fn read(&mut self) -> network::MessageResult {
-        network::read::read(&mut self.buffer).unwrap().parse()
+    /// Switches the connection to the game it logged in to, or tells why it couldn't
+    /// Round that was left in progress is shown right away
+    fn log_in(&mut self, result: LoginResult) {
+        match result {
+            Ok(login) => {
+                let (balance, round) = {
+                    let blackjack = login.blackjack.lock().unwrap();
+                    let round = if blackjack.legal_actions().is_empty() { None } else { Some(hand_state(&blackjack)) };
+                    (blackjack.balance(), round)
+                };
+
+                self.account = Some(login.account);
+                self.blackjack = Some(login.blackjack);
+                self.token = Some(login.token.clone());
+                self.send_message(ServerMessage::LoggedIn { balance });
+                self.send_event(ServerMessage::Session { token: login.token, expires_in: login.expires_in });
+                if let Some(round) = round {
+                    self.send_event(round);
+                }
+            },
+            Err(e) => {
+                let language = self.language;
+                self.send_message(ServerMessage::error(e, language));
+            }
+        }
+    }
+
+    fn log_out(&mut self) {
+        self.account = None;
+        self.blackjack = None;
+        self.token = None;
+        let language = self.language;
+        self.send_event(ServerMessage::error(Error::NotLoggedIn, language));
+    }
+
+    /// Name and state of the account the connection is logged in to
+    fn snapshot(&self) -> Option<(String, Account)> {
+        match (&self.account, &self.blackjack) {
+            (Some(name), Some(blackjack)) => Some((name.clone(), blackjack.lock().unwrap().account())),
+            _ => None,
+        }
+    }
+
+    /// Waits for the client's hello and answers it, the connection can't be used if this fails
+    fn handshake(&mut self) -> Result<(), String> {
+        let hello = match self.transport.read_frame(&TextCodec, self.max_frame_size) {
+            Ok(frame) => String::from_utf8_lossy(&frame)
+                .parse::<Handshake>()
+                .map_err(|_| String::from("Expected \"hello <version> <capabilities>\" as the first message")),
+            Err(e) => return Err(e.to_string()),
+        };
+
+        match hello.and_then(|hello| hello.accept().map(|_| hello)) {
+            Ok(hello) => {
+                self.send(Handshake::hello(capabilities()));
+
+                if hello.supports(&Capability::Binary) {
+                    self.protocol = Protocol::Binary;
+                } else if hello.supports(&Capability::Json) {
+                    self.protocol = Protocol::Json;
+                }
+
+                Ok(())
+            },
+            Err(reason) => {
+                self.send(Handshake::Reject(reason.clone()));
+                Err(reason)
+            }
+        }
+    }
+
+    /// Outer error means the stream itself is broken, inner one that the message couldn't be understood
+    fn read(&mut self) -> Result<network::MessageResult, network::read::Error> {
+        let waiting_since = Instant::now();
+        loop {
+            while let Ok(pushed) = self.events.try_recv() {
+                match pushed {
+                    Pushed::Event(event) => self.send_event(event),
+                    Pushed::LogOut => self.log_out(),
+                }
+            }
+
+            if self.transport.wait_for_frame(EVENT_POLL_INTERVAL)? {
+                break;
+            }
+
+            if self.idle_timeout.is_some_and(|timeout| waiting_since.elapsed() >= timeout) {
+                return Err(network::read::Error::from(network::read::ErrorKind::TimedOut));
+            }
+        }
+
+        let frame = self.transport.read_frame(self.protocol.codec(), self.max_frame_size)?;
+        let request = Request::decode_with(&frame, self.protocol, &self.aliases);
+        self.request_id = match &request {
+            Ok(request) => request.id,
+            // Message may be wrong while the id is fine, the error still answers the request
+            Err(_) => Request::decode_id(&frame, self.protocol),
+        };
+
+        Ok(request.map(|request| request.message))
     }
 
     fn close(&mut self) {
         info!(
             "Connection {} is closing.",
-            self.stream.peer_addr().unwrap()
+            self.transport.peer()
         );
-        self.stream.shutdown(Shutdown::Both).unwrap();
+
+        // Client hanging up first leaves nothing to shut down
+        if let Err(e) = self.transport.shutdown() {
+            debug!("Shutting down {} failed: {}", self.transport.peer(), e);
+        }
     }
 }
 
@@ -52,21 +281,105 @@ impl Drop for Connection {
 }
 
 type ConnectionWrapper = Arc<Mutex<Connection>>;
-type LoginResult = Result<BlackjackWrapper, String>;
+/// Game the connection logged in to and the token for coming back to it
+struct Login {
+    account: String,
+    blackjack: BlackjackWrapper,
+    token: String,
+    /// Seconds the token stays valid after the connection closes
+    expires_in: u64,
+}
+
+type LoginResult = Result<Login, Error>;
 
 /// Events that the threads can send to the server
 enum ServerEvent {
-    /// Store connection in the server
-    Add(ConnectionWrapper),
+    /// Store connection in the server with the sender of its events
+    Add(ConnectionWrapper, mpsc::Sender<Pushed>),
     /// Remove the connection from server
     Drop(ConnectionWrapper),
-    Login(String, mpsc::Sender<LoginResult>)
+    /// Connection logged in to the account with the session token and gets the changes its other connections make
+    LoggedIn(ConnectionWrapper, String, String),
+    /// Connection changed its game, the message goes to the other connections of the account
+    Changed(ConnectionWrapper, ServerMessage),
+    /// Stored password of the account, unless it's locked
+    Credential(String, mpsc::Sender<Result<Option<Credential>, Error>>),
+    /// Name and password of a new account, hashed by the connection
+    Register(String, Credential, mpsc::Sender<LoginResult>),
+    /// Name and the stored password that the one given matched, if any
+    Login(String, Option<Credential>, mpsc::Sender<LoginResult>),
+    /// Session token from an earlier connection
+    Resume(String, mpsc::Sender<LoginResult>),
+    /// Connection changing the password, name, the stored password that the current one matched and the new one
+    Passwd(ConnectionWrapper, String, Option<Credential>, Credential, mpsc::Sender<Result<(), Error>>),
+    /// Account changed and has to be written to the storage, the sender is told once it's done
+    Save(String, Account, mpsc::Sender<()>),
+}
+
+/// Checks the password against the stored one on the connection's thread, hashing on the event loop would hold up everyone
+/// Names without a password are checked against a dummy one, so they take as long to refuse
+fn check_password(sender: &mpsc::Sender<ServerEvent>, username: &str, password: &str) -> Result<Option<Credential>, Error> {
+    let (credential_sender, credential_receiver) = mpsc::channel();
+    sender.send(ServerEvent::Credential(username.to_string(), credential_sender)).unwrap();
+    let credential = credential_receiver.recv().unwrap()?;
+
+    let matched = credential.as_ref().unwrap_or_else(|| Credential::dummy()).verify(password);
+    Ok(credential.filter(|_| matched))
+}
+
+/// Has the server save the account if it's different from what it was before the command and waits until it's saved
+/// The change is only passed on to the other connections of the account after that, so they never see a state that wasn't saved
+fn save_changes(
+    sender: &mpsc::Sender<ServerEvent>,
+    before: Option<(String, Account)>,
+    connection: &Connection,
+    change: Option<(&ConnectionWrapper, ServerMessage)>,
+) {
+    let (name, blackjack) = match (&connection.account, &connection.blackjack) {
+        (Some(name), Some(blackjack)) => (name, blackjack),
+        _ => return,
+    };
+
+    // Game stays locked until the save is done, so a later change from another connection can't be saved before it
+    let blackjack = blackjack.lock().unwrap();
+    let account = blackjack.account();
+    if before != Some((name.clone(), account.clone())) {
+        let (saved_sender, saved_receiver) = mpsc::channel();
+        sender.send(ServerEvent::Save(name.clone(), account, saved_sender)).unwrap();
+        saved_receiver.recv().unwrap();
+    }
+
+    if let Some((connection, message)) = change {
+        sender.send(ServerEvent::Changed(connection.clone(), message)).unwrap();
+    }
+}
+
+/// Connection as the server sees it
+struct Peer {
+    connection: ConnectionWrapper,
+    events: mpsc::Sender<Pushed>,
+    account: Option<String>,
+    /// Session of the connection, which starts to expire once the connection closes
+    token: Option<String>,
 }
 
 pub struct Server<T> {
     address: T,
-    connections: Vec<ConnectionWrapper>,
-    blackjack_instances: HashMap<String, BlackjackWrapper>,
+    connections: Vec<Peer>,
+    /// Games that have been logged in to since the server started, shared by all connections using the account
+    games: HashMap<String, BlackjackWrapper>,
+    storage: Box<dyn Storage>,
+    lockouts: Lockouts,
+    sessions: Sessions,
+    rng: RngSource,
+    max_frame_size: u64,
+    idle_timeout: Option<Duration>,
+    idle_policy: IdlePolicy,
+    #[cfg(unix)]
+    unix_socket: Option<PathBuf>,
+    websocket_address: Option<String>,
+    tls: Option<(PathBuf, PathBuf)>,
+    aliases: Aliases,
 }
 
 impl<T> Server<T>
@@ -75,14 +388,96 @@ where
 {
     pub fn new(address: T) -> Server<T> {
         Server {
-            address: address,
+            address,
             connections: Vec::new(),
-            blackjack_instances: HashMap::new()
+            games: HashMap::new(),
+            storage: Box::new(MemoryStorage::new()),
+            lockouts: Lockouts::new(),
+            sessions: Sessions::new(Duration::from_secs(30 * 60)),
+            rng: RngSource::Thread,
+            max_frame_size: network::read::DEFAULT_MAX_FRAME_SIZE,
+            idle_timeout: Some(Duration::from_secs(60)),
+            idle_policy: IdlePolicy::Stand,
+            #[cfg(unix)]
+            unix_socket: None,
+            websocket_address: None,
+            tls: None,
+            aliases: Aliases::default(),
         }
     }
 
+    /// Where the accounts are kept, by default they're only kept in memory until the server stops
+    pub fn set_storage(&mut self, storage: impl Storage + 'static) {
+        self.storage = Box::new(storage);
+    }
+
+    /// How long players have to resume their session after getting disconnected
+    pub fn set_session_ttl(&mut self, ttl: Duration) {
+        self.sessions = Sessions::new(ttl);
+    }
+
+    /// Source of randomness for the shoes of new games
+    pub fn set_rng(&mut self, rng: RngSource) {
+        self.rng = rng;
+    }
+
+    /// Largest message a client may send, connections sending anything bigger are closed
+    pub fn set_max_frame_size(&mut self, max_frame_size: u64) {
+        self.max_frame_size = max_frame_size;
+    }
+
+    /// Connections that send nothing, not even a ping, for this long are closed. None keeps them open forever
+    pub fn set_idle_timeout(&mut self, idle_timeout: Option<Duration>) {
+        self.idle_timeout = idle_timeout;
+    }
+
+    /// How the ongoing rounds of clients closed for being idle are ended
+    pub fn set_idle_policy(&mut self, idle_policy: IdlePolicy) {
+        self.idle_policy = idle_policy;
+    }
+
+    /// Also accept local clients through a Unix domain socket at the path
+    #[cfg(unix)]
+    pub fn set_unix_socket(&mut self, path: impl Into<PathBuf>) {
+        self.unix_socket = Some(path.into());
+    }
+
+    /// Also accept browser clients over WebSocket on the address, every WebSocket message carries one message
+    pub fn set_websocket_address(&mut self, address: impl Into<String>) {
+        self.websocket_address = Some(address.into());
+    }
+
+    /// Encrypt the TCP and WebSocket connections with the PEM certificate chain and private key, local Unix sockets stay plain
+    pub fn set_tls(&mut self, certificate: impl Into<PathBuf>, key: impl Into<PathBuf>) {
+        self.tls = Some((certificate.into(), key.into()));
+    }
+
+    /// Shorthands text clients can type instead of the commands, "h" for "hit" etc. by default
+    pub fn set_aliases(&mut self, aliases: Aliases) {
+        self.aliases = aliases;
+    }
+
     /// Blocking call that starts the whole server
     pub fn start(&mut self) -> Result<(), io::Error> {
+        // Don't open any tables if the shuffles can't be trusted
+        match rng::self_test(&FisherYates, self.rng) {
+            Ok(statistic) => info!("Shuffle self-test passed using {} rng, chi-square statistic {:.1}", self.rng, statistic),
+            Err(e) => {
+                error!("{}", e);
+                return Err(io::Error::other(e));
+            }
+        }
+
+        // Bad certificate files should stop the server before anyone connects in cleartext
+        let tls = match &self.tls {
+            Some((certificate, key)) => {
+                let config = tls::server_config(certificate, key)?;
+                info!("Using TLS, certificate fingerprint: {}", tls::certificate_fingerprint(certificate)?);
+                Some(config)
+            },
+            None => None,
+        };
+
         let listener = TcpListener::bind(&self.address)?;
         info!(
             "Server connected to address: {}",
@@ -91,25 +486,94 @@ where
 
         let (sender, receiver): (mpsc::Sender<ServerEvent>, mpsc::Receiver<ServerEvent>) =
             mpsc::channel();
-        self.listen_incoming_connections(listener, sender);
+
+        #[cfg(unix)]
+        {
+            if let Some(path) = &self.unix_socket {
+                // Socket file of a previous run would make binding fail
+                if path.exists() {
+                    std::fs::remove_file(path)?;
+                }
+
+                let listener = UnixListener::bind(path)?;
+                info!("Server listening on unix socket: {}", path.display());
+
+                let incoming = iter::from_fn(move || {
+                    Some(listener.accept().and_then(|(stream, _)| StreamTransport::new(stream)).map(boxed))
+                });
+                self.listen_incoming_connections(incoming, sender.clone());
+            }
+        }
+
+        if let Some(address) = &self.websocket_address {
+            let listener = TcpListener::bind(address)?;
+            info!("Server listening for WebSockets on address: {}", listener.local_addr()?);
+
+            // Handshakes happen on the connection's own thread when it first reads
+            let max_frame_size = self.max_frame_size;
+            let tls = tls.clone();
+            let incoming = iter::from_fn(move || {
+                Some(
+                    listener
+                        .accept()
+                        .and_then(|(stream, _)| match &tls {
+                            Some(config) => TlsStream::accept(stream, config.clone())
+                                .and_then(|stream| WebSocketTransport::accept(stream, max_frame_size)),
+                            None => WebSocketTransport::accept(stream, max_frame_size),
+                        })
+                        .map(boxed),
+                )
+            });
+            self.listen_incoming_connections(incoming, sender.clone());
+        }
+
+        let incoming = iter::from_fn(move || {
+            Some(
+                listener
+                    .accept()
+                    .and_then(|(stream, _)| match &tls {
+                        // Handshake happens on the connection's own thread when it first reads
+                        Some(config) => TlsStream::accept(stream, config.clone()).and_then(StreamTransport::new).map(boxed),
+                        None => StreamTransport::new(stream).map(boxed),
+                    }),
+            )
+        });
+        self.listen_incoming_connections(incoming, sender);
 
         for event in receiver.iter() {
             match event {
-                ServerEvent::Add(connection) => self.push_connection(connection),
+                ServerEvent::Add(connection, events) => self.push_connection(connection, events),
                 ServerEvent::Drop(connection) => self.close_connection(connection),
-                ServerEvent::Login(username, login_sender) => {
-                    let username = username.to_lowercase();
-                    
-                    let instance = match self.blackjack_instances.get(&username) {
-                        Some(instance) => instance.clone(),
-                        None => {
-                            let instance = Arc::new(Mutex::new(Blackjack::new()));
-                            self.blackjack_instances.insert(username, instance.clone());
-                            instance
+                ServerEvent::LoggedIn(connection, username, token) => {
+                    if let Some(peer) = self.connections.iter_mut().find(|peer| Arc::ptr_eq(&peer.connection, &connection)) {
+                        peer.account = Some(username);
+                        // Session the connection had before is left like the connection was closed
+                        if let Some(previous) = peer.token.replace(token) {
+                            self.sessions.keep(&previous, Instant::now());
                         }
-                    };
-
-                    login_sender.send(Ok(instance)).unwrap();
+                    }
+                },
+                ServerEvent::Changed(connection, message) => self.share_change(&connection, message),
+                ServerEvent::Credential(username, credential_sender) => {
+                    credential_sender.send(self.credential(&username)).unwrap();
+                },
+                ServerEvent::Register(username, credential, login_sender) => {
+                    login_sender.send(self.register(&username, &credential)).unwrap();
+                },
+                ServerEvent::Login(username, matched, login_sender) => {
+                    login_sender.send(self.login(&username, matched)).unwrap();
+                },
+                ServerEvent::Resume(token, login_sender) => {
+                    login_sender.send(self.resume(&token)).unwrap();
+                },
+                ServerEvent::Passwd(connection, username, matched, new, passwd_sender) => {
+                    passwd_sender.send(self.change_password(&connection, &username, matched, &new)).unwrap();
+                },
+                ServerEvent::Save(username, account, saved_sender) => {
+                    if let Err(e) = self.storage.save(&username, &account) {
+                        error!("Unable to save the account of {}: {}", username, e);
+                    }
+                    saved_sender.send(()).unwrap();
                 }
             }
         }
@@ -117,28 +581,185 @@ where
         Ok(())
     }
 
+    /// Game of the account, from the ones being played, the storage or a new one
+    fn game(&mut self, username: &str) -> Result<BlackjackWrapper, Error> {
+        if let Some(instance) = self.games.get(username) {
+            return Ok(instance.clone());
+        }
+
+        let shoe = Shoe::new_with_rng(3, self.rng)?;
+        let blackjack = match self.storage.load(username) {
+            Some(account) => Blackjack::restore(shoe, &account).map_err(|e| {
+                error!("Saved game of {} is broken: {}", username, e);
+                Error::ServerError
+            })?,
+            None => Blackjack::with_shoe(shoe),
+        };
+
+        let instance = Arc::new(Mutex::new(blackjack));
+        self.games.insert(username.to_string(), instance.clone());
+        Ok(instance)
+    }
+
+    /// Names with an account are refused, even accounts saved before there were passwords
+    fn register(&mut self, username: &str, credential: &Credential) -> LoginResult {
+        if self.storage.credential(username).is_some() || self.storage.load(username).is_some() {
+            return Err(Error::AccountExists);
+        }
+
+        self.set_password(username, credential)?;
+        info!("Registered account {}", username);
+        self.start_session(username)
+    }
+
+    fn login(&mut self, username: &str, matched: Option<Credential>) -> LoginResult {
+        self.checked(username, matched)?;
+        self.start_session(username)
+    }
+
+    fn resume(&mut self, token: &str) -> LoginResult {
+        let username = self.sessions.take(token, Instant::now())?;
+        self.start_session(&username)
+    }
+
+    fn start_session(&mut self, username: &str) -> LoginResult {
+        Ok(Login {
+            account: username.to_string(),
+            blackjack: self.game(username)?,
+            token: self.sessions.issue(username, Instant::now())?,
+            expires_in: self.sessions.ttl().as_secs(),
+        })
+    }
+
+    /// Sessions of the account end and its other connections are logged out, except for the connection changing the password
+    fn change_password(&mut self, connection: &ConnectionWrapper, username: &str, matched: Option<Credential>, new: &Credential) -> Result<(), Error> {
+        self.checked(username, matched)?;
+        self.set_password(username, new)?;
+
+        let token = self.connections.iter().find(|peer| Arc::ptr_eq(&peer.connection, connection)).and_then(|peer| peer.token.clone());
+        self.sessions.revoke(username, token.as_deref());
+        for peer in self.connections.iter_mut() {
+            if peer.account.as_deref() == Some(username) && !Arc::ptr_eq(&peer.connection, connection) {
+                peer.account = None;
+                peer.token = None;
+                // Connection may be closing, its thread is gone along with the receiver
+                let _ = peer.events.send(Pushed::LogOut);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn credential(&mut self, username: &str) -> Result<Option<Credential>, Error> {
+        self.lockouts.check(username, Instant::now())?;
+        Ok(self.storage.credential(username))
+    }
+
+    /// Result of checking the password on the connection, which only counts if the password it matched is still the stored one
+    /// Wrong passwords count towards locking the account, names without one aren't tracked
+    fn checked(&mut self, username: &str, matched: Option<Credential>) -> Result<(), Error> {
+        let now = Instant::now();
+        self.lockouts.check(username, now)?;
+
+        match self.storage.credential(username) {
+            Some(credential) if matched.as_ref() == Some(&credential) => {
+                self.lockouts.succeeded(username);
+                Ok(())
+            },
+            Some(_) => {
+                warn!("Wrong password for account {}", username);
+                self.lockouts.failed(username, now);
+                Err(Error::InvalidCredentials)
+            },
+            None => Err(Error::InvalidCredentials),
+        }
+    }
+
+    fn set_password(&mut self, username: &str, credential: &Credential) -> Result<(), Error> {
+        self.storage.set_credential(username, credential).map_err(|e| {
+            error!("Unable to save the password of {}: {}", username, e);
+            Error::ServerError
+        })
+    }
+
     fn listen_incoming_connections(
         &self,
-        listener: TcpListener,
+        incoming: impl Iterator<Item = io::Result<Box<dyn Transport>>> + Send + 'static,
         sender: mpsc::Sender<ServerEvent>,
     ) {
+        let max_frame_size = self.max_frame_size;
+        let idle_timeout = self.idle_timeout;
+        let idle_policy = self.idle_policy;
+        let aliases = self.aliases.clone();
         thread::spawn(move || {
-            for stream in listener.incoming() {
-                let stream = stream.unwrap();
-                info!("New incoming connection: {}", stream.peer_addr().unwrap());
+            for transport in incoming {
+                let transport = match transport {
+                    Ok(transport) => transport,
+                    Err(e) => {
+                        warn!("Accepting a connection failed: {}", e);
+                        continue;
+                    }
+                };
+                info!("New incoming connection: {}", transport.peer());
 
-                let mut connection = Connection::new(stream);
-                connection.send("Type \"exit\" any time to stop the process. Type \"login <name>\" to start a new game (or if such a name already exists, resume the game).");
-                let connection = Arc::new(Mutex::new(connection));
+                let (events, receiver) = mpsc::channel();
+                let connection = Arc::new(Mutex::new(Connection::new(transport, max_frame_size, idle_timeout, aliases.clone(), receiver)));
                 let thread_connection = connection.clone();
-                sender.send(ServerEvent::Add(connection)).unwrap();
+                sender.send(ServerEvent::Add(connection, events)).unwrap();
 
                 let sender = sender.clone();
 
                 thread::spawn(move || {
+                    {
+                        let mut connection = thread_connection.lock().unwrap();
+                        match connection.handshake() {
+                            Ok(_) => connection.send_event(ServerMessage::Welcome),
+                            Err(e) => {
+                                warn!("Handshake with {} failed: {}", connection.transport.peer(), e);
+                                sender.send(ServerEvent::Drop(thread_connection.clone())).unwrap();
+                                return;
+                            }
+                        }
+                    }
+
                     while let Ok(connection) = thread_connection.lock() {
                         let mut connection = connection;
-                        match connection.read() {
+                        let message = match connection.read() {
+                            Ok(message) => message,
+                            Err(e) => {
+                                // Rest of the stream can't be split into messages anymore
+                                match e.kind() {
+                                    network::read::ErrorKind::ConnectionLost => info!("{}", e),
+                                    network::read::ErrorKind::IOError | network::read::ErrorKind::TruncatedFrame => warn!("{}", e),
+                                    // Client is still there to be told why it gets disconnected
+                                    network::read::ErrorKind::FrameTooLarge | network::read::ErrorKind::InvalidBytesRead => {
+                                        warn!("{}", e);
+                                        let language = connection.language;
+                                        connection.send_message(ServerMessage::error(Error::InvalidFrame, language));
+                                    },
+                                    network::read::ErrorKind::TimedOut => {
+                                        info!("Closing idle connection {}", connection.transport.peer());
+                                        let before = connection.snapshot();
+                                        let result = connection.abandon_round(idle_policy);
+                                        save_changes(&sender, before, &connection, result.map(|result| (&thread_connection, result)));
+                                        let language = connection.language;
+                                        connection.send_event(ServerMessage::error(Error::IdleTimeout, language));
+                                    },
+                                }
+
+                                sender.send(ServerEvent::Drop(thread_connection.clone())).unwrap();
+                                break;
+                            }
+                        };
+
+                        let shoe_state = connection.shoe_state();
+                        let before = connection.snapshot();
+                        let token = connection.token.clone();
+                        let language = connection.language;
+                        connection.reply = None;
+                        connection.held = Some(Vec::new());
+
+                        match message {
                             Ok(message) => match message {
                                 network::Message::Exit => {
                                     sender
@@ -146,111 +767,280 @@ where
                                         .unwrap();
                                     break;
                                 },
-                                network::Message::Login(login) => {
+                                network::Message::Register { name, password } => {
+                                    let login = Credential::new(&password).and_then(|credential| {
+                                        let (login_sender, login_receiver) = mpsc::channel();
+                                        sender.send(ServerEvent::Register(name.to_lowercase(), credential, login_sender)).unwrap();
+                                        login_receiver.recv().unwrap()
+                                    });
+                                    connection.log_in(login);
+                                },
+                                network::Message::Login { name, password } => {
+                                    let name = name.to_lowercase();
+                                    let login = check_password(&sender, &name, &password).and_then(|matched| {
+                                        let (login_sender, login_receiver) = mpsc::channel();
+                                        sender.send(ServerEvent::Login(name, matched, login_sender)).unwrap();
+                                        login_receiver.recv().unwrap()
+                                    });
+                                    connection.log_in(login);
+                                },
+                                network::Message::Resume(token) => {
                                     let (login_sender, login_receiver) = mpsc::channel();
-                                    sender.send(ServerEvent::Login(login, login_sender)).unwrap();
-                                    match login_receiver.recv() {
-                                        Ok(result) => match result {
-                                            Ok(blackjack) => {
-                                                connection.blackjack = Some(blackjack.clone());
-                                                let blackjack = blackjack.lock().unwrap();
-                                                connection.send(format!("Successfully logged in. Current balance: {}. Type \"deposit <int>\" to add to your balance or \"start <int>\" to start a new game", blackjack.balance()));
-                                            },
-                                            Err(e) => {
-                                                warn!("{}", e);
-                                                connection.send(e);
+                                    sender.send(ServerEvent::Resume(token, login_sender)).unwrap();
+                                    connection.log_in(login_receiver.recv().unwrap());
+                                },
+                                network::Message::Passwd { current, new } => {
+                                    let response = match connection.account.clone() {
+                                        Some(account) => {
+                                            // New password is hashed first, so a weak one is refused without checking the current one
+                                            let changed = Credential::new(&new).and_then(|new| {
+                                                let matched = check_password(&sender, &account, &current)?;
+                                                let (passwd_sender, passwd_receiver) = mpsc::channel();
+                                                sender.send(ServerEvent::Passwd(thread_connection.clone(), account, matched, new, passwd_sender)).unwrap();
+                                                passwd_receiver.recv().unwrap()
+                                            });
+                                            match changed {
+                                                Ok(_) => ServerMessage::PasswordChanged,
+                                                Err(e) => ServerMessage::error(e, language),
                                             }
                                         },
-                                        Err(e) =>  {
-                                            panic!(e)
-                                        }
-                                    }
+                                        None => ServerMessage::error(Error::NotLoggedIn, language)
+                                    };
+
+                                    connection.send_message(response);
+                                },
+                                network::Message::Ping => connection.send_message(ServerMessage::Pong),
+                                network::Message::Protocol(protocol) => {
+                                    connection.protocol = protocol;
+                                    connection.send_message(ServerMessage::ProtocolChanged { protocol });
+                                },
+                                network::Message::Help(command) => {
+                                    let available = match &connection.blackjack {
+                                        Some(blackjack) => available_commands(Some(&blackjack.lock().unwrap())),
+                                        None => available_commands(None),
+                                    };
+
+                                    let commands = match command {
+                                        Some(command) => vec![command],
+                                        None => available.clone(),
+                                    };
+                                    let commands = commands
+                                        .into_iter()
+                                        .map(|command| CommandHelp {
+                                            command,
+                                            usage: catalog::usage(command, language),
+                                            description: catalog::description(command, language).to_string(),
+                                            available: available.contains(&command),
+                                        })
+                                        .collect();
+
+                                    connection.send_message(ServerMessage::Help { commands });
+                                },
+                                network::Message::Lang(language) => {
+                                    connection.language = language;
+                                    connection.send_message(ServerMessage::LanguageChanged { language });
                                 },
                                 network::Message::Balance => {
-                                    let message = match &connection.blackjack {
-                                        Some(blackjack) => blackjack.lock().unwrap().balance().to_string(),
-                                        None => "Not logged in".to_string()
+                                    let response = match &connection.blackjack {
+                                        Some(blackjack) => ServerMessage::Balance { balance: blackjack.lock().unwrap().balance() },
+                                        None => ServerMessage::error(Error::NotLoggedIn, language)
                                     };
 
-                                    connection.send(message);
+                                    connection.send_message(response);
                                 }
                                 network::Message::Deposit(amount) => {
-                                    // message variable is necessary since it's not possible to send message to the connection in the match blocks
+                                    // response variable is necessary since it's not possible to send message to the connection in the match blocks
                                     // &connection.blackjack accesses the connection in an immutable context so it's not possible to do any mutable operations on it
-                                    let message = match &connection.blackjack {
+                                    let response = match &connection.blackjack {
                                         Some(blackjack) => {
                                             let mut blackjack = blackjack.lock().unwrap();
                                             match blackjack.deposit(amount) {
-                                                Ok(_) => format!("{} deposited to the account", amount),
+                                                Ok(_) => ServerMessage::Deposited { amount, balance: blackjack.balance() },
                                                 Err(e) => {
                                                     warn!("{}", e);
-                                                    e.to_string()
+                                                    ServerMessage::error(e, language)
                                                 }
                                             }
                                         },
-                                        None => "Not logged in".to_string()
+                                        None => ServerMessage::error(Error::NotLoggedIn, language)
                                     };
 
-                                    connection.send(message);
+                                    connection.send_message(response);
                                 },
                                 network::Message::Start(amount) => {
-                                    let message = match &connection.blackjack {
+                                    let response = match &connection.blackjack {
                                         Some(blackjack) => {
                                             let mut blackjack = blackjack.lock().unwrap();
                                             match blackjack.start(amount) {
-                                                Ok(_) => format!("Dealer Hand: {}\r\nPlayer Hand: {}\r\nType \"hit\" or \"stand\"", blackjack.dealer_total(), blackjack.player_total()),
+                                                Ok(_) => {
+                                                    if blackjack.is_betting_with_count() {
+                                                        warn!("Connection {} bets are following the count", connection.transport.peer());
+                                                    }
+
+                                                    hand_state(&blackjack)
+                                                },
+                                                Err(e) => {
+                                                    warn!("{}", e);
+                                                    ServerMessage::error(e, language)
+                                                }
+                                            }
+                                        },
+                                        None => ServerMessage::error(Error::NotLoggedIn, language)
+                                    };
+
+                                    connection.send_message(response);
+                                },
+                                network::Message::Rebet => {
+                                    let response = match &connection.blackjack {
+                                        Some(blackjack) => {
+                                            let mut blackjack = blackjack.lock().unwrap();
+                                            match blackjack.rebet() {
+                                                Ok(_) => hand_state(&blackjack),
                                                 Err(e) => {
                                                     warn!("{}", e);
-                                                    e.to_string()
+                                                    ServerMessage::error(e, language)
                                                 }
                                             }
                                         },
-                                        None => "Not logged in".to_string()
+                                        None => ServerMessage::error(Error::NotLoggedIn, language)
                                     };
 
-                                    connection.send(message);
+                                    connection.send_message(response);
                                 },
                                 network::Message::Hit => {
-                                    let message = match &connection.blackjack {
+                                    let response = match &connection.blackjack {
                                         Some(blackjack) => {
                                             let mut blackjack = blackjack.lock().unwrap();
                                             match blackjack.hit() {
                                                 Ok(result) => match result {
-                                                    blackjack::HitResult::Continue => format!("Dealer Hand: {}\r\nPlayer Hand: {}\r\nType \"hit\" or \"stand\"", blackjack.dealer_total(), blackjack.player_total()),
-                                                    blackjack::HitResult::Bust => format!("Bust! Dealer Hand: {}\r\nPlayer Hand: {}\r\nType \"start <int>\" to start a new game", blackjack.dealer_total(), blackjack.player_total()),
+                                                    blackjack::HitResult::Continue => hand_state(&blackjack),
+                                                    blackjack::HitResult::Bust => round_result(&blackjack, Outcome::Bust),
                                                 },
                                                 Err(e) => {
                                                     warn!("{}", e);
-                                                    e.to_string()
+                                                    ServerMessage::error(e, language)
                                                 }
                                             }
                                         },
-                                        None => "Not logged in".to_string()
+                                        None => ServerMessage::error(Error::NotLoggedIn, language)
                                     };
 
-                                    connection.send(message);
+                                    connection.send_message(response);
                                 },
                                 network::Message::Stand => {
-                                    let message = match &connection.blackjack {
+                                    let response = match &connection.blackjack {
                                         Some(blackjack) => {
                                             let mut blackjack = blackjack.lock().unwrap();
                                             match blackjack.stand() {
-                                                Ok(winner) => format!("{}! Dealer Hand: {}\r\nPlayer Hand: {}\r\nType \"start <int>\" to start a new game", winner, blackjack.dealer_total(), blackjack.player_total()),
+                                                Ok(winner) => round_result(&blackjack, outcome(&blackjack, winner)),
                                                 Err(e) => {
                                                     warn!("{}", e);
-                                                    e.to_string()
+                                                    ServerMessage::error(e, language)
                                                 }
                                             }
                                         },
-                                        None => "Not logged in".to_string()
+                                        None => ServerMessage::error(Error::NotLoggedIn, language)
                                     };
 
-                                    connection.send(message);
+                                    connection.send_message(response);
+                                },
+                                network::Message::Double => {
+                                    let response = match &connection.blackjack {
+                                        Some(blackjack) => {
+                                            let mut blackjack = blackjack.lock().unwrap();
+                                            match blackjack.double() {
+                                                Ok(winner) => round_result(&blackjack, outcome(&blackjack, winner)),
+                                                Err(e) => {
+                                                    warn!("{}", e);
+                                                    ServerMessage::error(e, language)
+                                                }
+                                            }
+                                        },
+                                        None => ServerMessage::error(Error::NotLoggedIn, language)
+                                    };
+
+                                    connection.send_message(response);
+                                },
+                                network::Message::Surrender => {
+                                    let response = match &connection.blackjack {
+                                        Some(blackjack) => {
+                                            let mut blackjack = blackjack.lock().unwrap();
+                                            match blackjack.surrender() {
+                                                Ok(_) => round_result(&blackjack, Outcome::Surrendered),
+                                                Err(e) => {
+                                                    warn!("{}", e);
+                                                    ServerMessage::error(e, language)
+                                                }
+                                            }
+                                        },
+                                        None => ServerMessage::error(Error::NotLoggedIn, language)
+                                    };
+
+                                    connection.send_message(response);
+                                },
+                                network::Message::Count => {
+                                    let response = match &connection.blackjack {
+                                        Some(blackjack) => {
+                                            let blackjack = blackjack.lock().unwrap();
+                                            let counts = CountingSystem::ALL
+                                                .iter()
+                                                .map(|system| {
+                                                    let count = blackjack.count(*system);
+                                                    SystemCount { system: system.to_string(), running: count.running, true_count: count.true_count }
+                                                })
+                                                .collect();
+
+                                            ServerMessage::Count { counts }
+                                        },
+                                        None => ServerMessage::error(Error::NotLoggedIn, language)
+                                    };
+
+                                    connection.send_message(response);
+                                },
+                                network::Message::Odds => {
+                                    let response = match &connection.blackjack {
+                                        Some(blackjack) => {
+                                            let blackjack = blackjack.lock().unwrap();
+                                            match blackjack.dealer_odds() {
+                                                Ok(odds) => ServerMessage::Odds {
+                                                    upcard: blackjack.dealer_upcard().unwrap().to_string(),
+                                                    totals: odds.totals().map(|(total, probability)| TotalProbability { total, probability }).collect(),
+                                                    bust: odds.bust(),
+                                                },
+                                                Err(e) => {
+                                                    warn!("{}", e);
+                                                    ServerMessage::error(e, language)
+                                                }
+                                            }
+                                        },
+                                        None => ServerMessage::error(Error::NotLoggedIn, language)
+                                    };
+
+                                    connection.send_message(response);
                                 }
                             },
                             Err(e) => {
                                 warn!("{}", e);
-                                connection.send("Server couldn't understand the command");
+                                connection.send_message(ServerMessage::error(e.error(), language));
+                            }
+                        }
+
+                        if let (Some(account), Some(new)) = (&connection.account, &connection.token) {
+                            if token.as_ref() != Some(new) {
+                                sender.send(ServerEvent::LoggedIn(thread_connection.clone(), account.clone(), new.clone())).unwrap();
+                            }
+                        }
+                        // Client only hears about the changes once they're saved
+                        let change = connection.reply.take().filter(changes_game);
+                        save_changes(&sender, before, &connection, change.map(|reply| (&thread_connection, reply)));
+                        for response in connection.held.take().unwrap_or_default() {
+                            connection.write_response(response);
+                        }
+
+                        // Shoe ran out while dealing for the command
+                        if let (Some((before, shuffles)), Some((after, current))) = (shoe_state, connection.shoe_state()) {
+                            if Arc::ptr_eq(&before, &after) && current > shuffles {
+                                connection.send_event(ServerMessage::Shuffled);
                             }
                         }
                     }
@@ -259,13 +1049,106 @@ where
         });
     }
 
-    fn push_connection(&mut self, connection: ConnectionWrapper) {
-        self.connections.push(connection);
+    fn push_connection(&mut self, connection: ConnectionWrapper, events: mpsc::Sender<Pushed>) {
+        self.connections.push(Peer { connection, events, account: None, token: None });
+    }
+
+    /// Sends the change as an event to the other connections logged in to the same account
+    fn share_change(&self, connection: &ConnectionWrapper, message: ServerMessage) {
+        let account = match self.connections.iter().find(|peer| Arc::ptr_eq(&peer.connection, connection)) {
+            Some(Peer { account: Some(account), .. }) => account,
+            _ => return,
+        };
+
+        for peer in self.connections.iter() {
+            if peer.account.as_ref() == Some(account) && !Arc::ptr_eq(&peer.connection, connection) {
+                // Connection may be closing, its thread is gone along with the receiver
+                let _ = peer.events.send(Pushed::Event(message.clone()));
+            }
+        }
     }
 
     fn close_connection(&mut self, connection: ConnectionWrapper) {
+        if let Some(token) = self.connections.iter().find(|peer| Arc::ptr_eq(&peer.connection, &connection)).and_then(|peer| peer.token.as_ref()) {
+            self.sessions.keep(token, Instant::now());
+        }
+
+
         // No need to manually close the connection since it gets dropped anyway once all arc pointers are gone
         self.connections
-            .retain(|conn| !Arc::ptr_eq(&connection, conn));
+            .retain(|peer| !Arc::ptr_eq(&connection, &peer.connection));
+    }
+}
+
+/// Commands that make sense in the state of the game, the game itself tells which actions the round allows
+fn available_commands(blackjack: Option<&Blackjack>) -> Vec<Command> {
+    let mut available = vec![Command::Register, Command::Login, Command::Resume, Command::Lang, Command::Protocol, Command::Ping, Command::Help, Command::Exit];
+
+    if let Some(blackjack) = blackjack {
+        available.extend_from_slice(&[Command::Passwd, Command::Balance, Command::Deposit, Command::Count]);
+
+        let actions = blackjack.legal_actions();
+        if actions.is_empty() {
+            available.push(Command::Start);
+            if blackjack.last_bet().is_some() {
+                available.push(Command::Rebet);
+            }
+        } else {
+            available.push(Command::Odds);
+        }
+
+        // Actions are named after the commands that take them
+        available.extend(actions.iter().filter_map(|legal| legal.action.to_string().parse::<Command>().ok()));
     }
+
+    Command::ALL.iter().copied().filter(|command| available.contains(command)).collect()
+}
+
+/// Replies to commands that changed the game, the other connections of the account get them as events
+fn changes_game(message: &ServerMessage) -> bool {
+    matches!(message, ServerMessage::Deposited { .. } | ServerMessage::HandState { .. } | ServerMessage::RoundResult { .. })
+}
+
+fn boxed(transport: impl Transport + 'static) -> Box<dyn Transport> {
+    Box::new(transport)
+}
+
+fn hand(cards: &[Card], total: u8) -> Hand {
+    Hand {
+        cards: cards.iter().map(|card| card.to_string()).collect(),
+        total,
+    }
+}
+
+/// Dealer's hole card stays face down, only the round result shows the whole hand
+fn hand_state(blackjack: &Blackjack) -> ServerMessage {
+    let upcard = blackjack.dealer_upcard().unwrap();
+    ServerMessage::HandState {
+        dealer: hand(std::slice::from_ref(upcard), upcard.value().value()),
+        player: hand(blackjack.player_hand(), blackjack.player_total()),
+    }
+}
+
+fn outcome(blackjack: &Blackjack, winner: blackjack::Winner) -> Outcome {
+    match winner {
+        blackjack::Winner::Player => Outcome::PlayerWon,
+        // Dealer doesn't play against a busted double
+        blackjack::Winner::Dealer if blackjack.player_total() > 21 => Outcome::Bust,
+        blackjack::Winner::Dealer => Outcome::DealerWon,
+        blackjack::Winner::Draw => Outcome::Draw,
+    }
+}
+
+fn round_result(blackjack: &Blackjack, outcome: Outcome) -> ServerMessage {
+    ServerMessage::RoundResult {
+        outcome,
+        dealer: hand(blackjack.dealer_hand(), blackjack.dealer_total()),
+        player: hand(blackjack.player_hand(), blackjack.player_total()),
+        balance: blackjack.balance(),
+    }
+}
+
+/// Capabilities the server advertises in its hello
+fn capabilities() -> Vec<Capability> {
+    vec![Capability::Json, Capability::Binary]
 }
@@ -0,0 +1,224 @@
+use crate::deck::Card;
+use rand::{seq::SliceRandom, Rng, RngCore};
+
+/// Strategy the shoe uses to shuffle its cards when refilling
+pub trait Shuffle: Send {
+    fn shuffle(&self, cards: &mut Vec<Card>, rng: &mut dyn RngCore);
+}
+
+/// Perfect uniform shuffle
+pub struct FisherYates;
+
+impl Shuffle for FisherYates {
+    fn shuffle(&self, cards: &mut Vec<Card>, rng: &mut dyn RngCore) {
+        cards.shuffle(rng);
+    }
+}
+
+/// Gilbert–Shannon–Reeds model of a riffle shuffle done by hand
+pub struct Riffle {
+    passes: u8,
+}
+
+impl Riffle {
+    pub fn new(passes: u8) -> Self {
+        Self { passes }
+    }
+
+    fn pass(cards: &mut Vec<Card>, rng: &mut dyn RngCore) {
+        // Cut point is binomially distributed, same as flipping a coin for every card
+        let total = cards.len();
+        let cut = (0..total).filter(|_| rng.gen_bool(0.5)).count();
+        let right = cards.split_off(cut);
+        let left = std::mem::replace(cards, Vec::with_capacity(total));
+
+        let mut left = left.into_iter();
+        let mut right = right.into_iter();
+        let (mut left_count, mut right_count) = (cut, total - cut);
+
+        // Next card drops from a packet with probability proportional to the packet's size
+        while left_count > 0 && right_count > 0 {
+            if rng.gen_range(0, left_count + right_count) < left_count {
+                cards.extend(left.next());
+                left_count -= 1;
+            } else {
+                cards.extend(right.next());
+                right_count -= 1;
+            }
+        }
+
+        cards.extend(left);
+        cards.extend(right);
+    }
+}
+
+impl Shuffle for Riffle {
+    fn shuffle(&self, cards: &mut Vec<Card>, rng: &mut dyn RngCore) {
+        for _ in 0..self.passes {
+            Self::pass(cards, rng);
+        }
+    }
+}
+
+/// Strip shuffle, the cards are split into packets at random points and the order of the packets is reversed
+pub struct Strip {
+    packets: usize,
+}
+
+impl Strip {
+    pub fn new(packets: usize) -> Self {
+        Self { packets }
+    }
+}
+
+impl Shuffle for Strip {
+    fn shuffle(&self, cards: &mut Vec<Card>, rng: &mut dyn RngCore) {
+        if cards.len() < 2 || self.packets < 2 {
+            return;
+        }
+
+        let cut_count = (self.packets - 1).min(cards.len() - 1);
+        let mut cuts = rand::seq::index::sample(rng, cards.len() - 1, cut_count).into_vec();
+        // Sampled indices are in 0..len-1, shift them so that no packet is empty
+        cuts.iter_mut().for_each(|cut| *cut += 1);
+        cuts.sort_unstable();
+
+        let mut packets = Vec::with_capacity(cuts.len() + 1);
+        for cut in cuts.iter().rev() {
+            packets.push(cards.split_off(*cut));
+        }
+        packets.push(std::mem::take(cards));
+
+        for packet in packets {
+            cards.extend(packet);
+        }
+    }
+}
+
+/// Single cut of the cards at a random point
+pub struct Cut;
+
+impl Shuffle for Cut {
+    fn shuffle(&self, cards: &mut Vec<Card>, rng: &mut dyn RngCore) {
+        if cards.len() < 2 {
+            return;
+        }
+
+        let cut = rng.gen_range(1, cards.len());
+        cards.rotate_left(cut);
+    }
+}
+
+/// Multiple shuffles done one after another
+pub struct Procedure {
+    steps: Vec<Box<dyn Shuffle>>,
+}
+
+impl Procedure {
+    pub fn new(steps: Vec<Box<dyn Shuffle>>) -> Self {
+        Self { steps }
+    }
+
+    /// Riffle, riffle, strip, riffle and cut, a common procedure for hand shuffled games
+    pub fn casino() -> Self {
+        Self::new(vec![
+            Box::new(Riffle::new(2)),
+            Box::new(Strip::new(5)),
+            Box::new(Riffle::new(1)),
+            Box::new(Cut),
+        ])
+    }
+}
+
+impl Shuffle for Procedure {
+    fn shuffle(&self, cards: &mut Vec<Card>, rng: &mut dyn RngCore) {
+        for step in self.steps.iter() {
+            step.shuffle(cards, rng);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::deck::Deck;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    fn rng() -> StdRng {
+        StdRng::seed_from_u64(2024)
+    }
+
+    /// Positions the shuffled cards had in the unshuffled deck
+    fn positions(shuffle: &dyn Shuffle) -> Vec<usize> {
+        let deck = Deck::new();
+        let mut cards = deck.cards().to_vec();
+        shuffle.shuffle(&mut cards, &mut rng());
+
+        cards
+            .iter()
+            .map(|card| deck.cards().iter().position(|c| c == card).unwrap())
+            .collect()
+    }
+
+    fn assert_permutation(positions: &[usize]) {
+        let mut sorted = positions.to_vec();
+        sorted.sort_unstable();
+        assert_eq!(sorted, (0..positions.len()).collect::<Vec<usize>>());
+    }
+
+    /// Number of increasing runs of consecutive original positions, a single riffle produces at most 2
+    fn rising_sequences(positions: &[usize]) -> usize {
+        let mut index = vec![0; positions.len()];
+        for (i, position) in positions.iter().enumerate() {
+            index[*position] = i;
+        }
+
+        1 + index.windows(2).filter(|pair| pair[1] < pair[0]).count()
+    }
+
+    #[test]
+    fn shuffles_keep_all_cards() {
+        let shuffles: Vec<Box<dyn Shuffle>> = vec![
+            Box::new(FisherYates),
+            Box::new(Riffle::new(7)),
+            Box::new(Strip::new(8)),
+            Box::new(Cut),
+            Box::new(Procedure::casino()),
+        ];
+
+        for shuffle in shuffles.iter() {
+            assert_permutation(&positions(shuffle.as_ref()));
+        }
+    }
+
+    #[test]
+    fn riffle_without_passes_keeps_order() {
+        let positions = positions(&Riffle::new(0));
+        assert_eq!(positions, (0..52).collect::<Vec<usize>>());
+    }
+
+    #[test]
+    fn single_riffle_has_two_rising_sequences() {
+        let positions = positions(&Riffle::new(1));
+        assert!(rising_sequences(&positions) <= 2);
+    }
+
+    #[test]
+    fn strip_reverses_packets() {
+        let positions = positions(&Strip::new(4));
+        // Every packet keeps its inner order, so there are exactly as many runs as packets
+        let runs = 1 + positions.windows(2).filter(|pair| pair[1] != pair[0] + 1).count();
+        assert_eq!(runs, 4);
+    }
+
+    #[test]
+    fn cut_rotates_cards() {
+        let positions = positions(&Cut);
+        let start = positions[0];
+        assert_ne!(start, 0);
+
+        for (i, position) in positions.iter().enumerate() {
+            assert_eq!(*position, (start + i) % 52);
+        }
+    }
+}
@@ -0,0 +1,170 @@
+use crate::account::Account;
+use crate::auth::Credential;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, Write};
+#[cfg(unix)]
+use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+use std::path::PathBuf;
+
+/// Account and its password the way they're stored, the game doesn't know about the password
+#[derive(Serialize, Deserialize, Default, Clone)]
+struct Record {
+    #[serde(flatten)]
+    account: Account,
+    /// Accounts from before passwords have none, nobody can log in to them until the server is run with `--set-password`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    password: Option<Credential>,
+}
+
+/// Where the accounts are kept between restarts of the server
+pub trait Storage: Send {
+    fn load(&self, name: &str) -> Option<Account>;
+
+    /// Called every time the account changes, before the player is told about the change
+    /// The change has to be persisted before returning
+    fn save(&mut self, name: &str, account: &Account) -> io::Result<()>;
+
+    fn credential(&self, name: &str) -> Option<Credential>;
+
+    /// Sets the password of the account, creating an empty account if there isn't one
+    fn set_credential(&mut self, name: &str, credential: &Credential) -> io::Result<()>;
+}
+
+/// Accounts that are gone once the server stops, for tests and throwaway tables
+#[derive(Default)]
+pub struct MemoryStorage {
+    accounts: HashMap<String, Record>,
+}
+
+impl MemoryStorage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl Storage for MemoryStorage {
+    fn load(&self, name: &str) -> Option<Account> {
+        self.accounts.get(name).map(|record| record.account.clone())
+    }
+
+    fn save(&mut self, name: &str, account: &Account) -> io::Result<()> {
+        self.accounts.entry(name.to_string()).or_default().account = account.clone();
+        Ok(())
+    }
+
+    fn credential(&self, name: &str) -> Option<Credential> {
+        self.accounts.get(name).and_then(|record| record.password.clone())
+    }
+
+    fn set_credential(&mut self, name: &str, credential: &Credential) -> io::Result<()> {
+        self.accounts.entry(name.to_string()).or_default().password = Some(credential.clone());
+        Ok(())
+    }
+}
+
+/// Accounts in a JSON file, read once when opened and rewritten on every change
+pub struct FileStorage {
+    path: PathBuf,
+    accounts: HashMap<String, Record>,
+}
+
+impl FileStorage {
+    /// Reads the accounts from the file, a missing file is created on the first save
+    pub fn open(path: impl Into<PathBuf>) -> io::Result<Self> {
+        let path = path.into();
+        let accounts = match fs::read_to_string(&path) {
+            Ok(json) => serde_json::from_str(&json).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => HashMap::new(),
+            Err(e) => return Err(e),
+        };
+
+        Ok(Self { path, accounts })
+    }
+
+    /// Renaming over the old file means a crash mid-write can't leave half of it behind
+    /// Only the server's user can read the file, it has the password hashes
+    fn write(&self) -> io::Result<()> {
+        let json = serde_json::to_string_pretty(&self.accounts).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        let temporary = self.path.with_extension("tmp");
+
+        let mut options = fs::OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        options.mode(0o600);
+
+        let mut file = options.open(&temporary)?;
+        // Mode is only used for new files, one left over from a crash may have been readable by others
+        #[cfg(unix)]
+        file.set_permissions(fs::Permissions::from_mode(0o600))?;
+        file.write_all(json.as_bytes())?;
+        fs::rename(&temporary, &self.path)
+    }
+
+    pub fn len(&self) -> usize {
+        self.accounts.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.accounts.is_empty()
+    }
+}
+
+impl Storage for FileStorage {
+    fn load(&self, name: &str) -> Option<Account> {
+        self.accounts.get(name).map(|record| record.account.clone())
+    }
+
+    fn save(&mut self, name: &str, account: &Account) -> io::Result<()> {
+        self.accounts.entry(name.to_string()).or_default().account = account.clone();
+        self.write()
+    }
+
+    fn credential(&self, name: &str) -> Option<Credential> {
+        self.accounts.get(name).and_then(|record| record.password.clone())
+    }
+
+    fn set_credential(&mut self, name: &str, credential: &Credential) -> io::Result<()> {
+        self.accounts.entry(name.to_string()).or_default().password = Some(credential.clone());
+        self.write()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::account::Round;
+
+    #[test]
+    fn file_storage() {
+        let path = std::env::temp_dir().join(format!("blackjack-accounts-{}.json", std::process::id()));
+        let _ = fs::remove_file(&path);
+
+        let account = Account {
+            balance: 90,
+            last_bet: Some(10),
+            round: Some(Round { bet: 10, dealer: String::from("TH 9S"), player: String::from("2D 9C") }),
+        };
+
+        let mut storage = FileStorage::open(&path).unwrap();
+        assert!(storage.is_empty());
+        storage.save("alice", &account).unwrap();
+        storage.save("bob", &Account::default()).unwrap();
+        let credential = Credential::with_iterations("password", 10).unwrap();
+        storage.set_credential("alice", &credential).unwrap();
+
+        let storage = FileStorage::open(&path).unwrap();
+        assert_eq!(2, storage.len());
+        assert_eq!(Some(account), storage.load("alice"));
+        assert_eq!(Some(credential), storage.credential("alice"));
+        assert_eq!(None, storage.credential("bob"));
+        assert_eq!(None, storage.load("carol"));
+        #[cfg(unix)]
+        assert_eq!(0o600, fs::metadata(&path).unwrap().permissions().mode() & 0o777);
+
+        fs::write(&path, "not json").unwrap();
+        assert_eq!(io::ErrorKind::InvalidData, FileStorage::open(&path).err().unwrap().kind());
+        fs::remove_file(&path).unwrap();
+    }
+}
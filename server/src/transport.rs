@@ -0,0 +1,332 @@
+use log::*;
+use network::codec::Codec;
+use network::read;
+use network::tls::TlsStream;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::mem;
+use std::net::{Shutdown, TcpStream};
+use std::time::Duration;
+#[cfg(unix)]
+use std::os::unix::net::UnixStream;
+use tungstenite::protocol::WebSocketConfig;
+use tungstenite::{Message as WebSocketMessage, WebSocket};
+
+/// Byte stream the messages are split from with a codec
+pub trait Stream: Read + Write + Send {
+    /// Second handle to the same stream, so that it can be read from and written to separately
+    fn try_clone(&self) -> io::Result<Box<dyn Stream>>;
+
+    /// Other end of the stream, for logging
+    fn peer(&self) -> String;
+
+    fn shutdown(&self) -> io::Result<()>;
+
+    /// Reads fail with `WouldBlock` or `TimedOut` when nothing arrives in time
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()>;
+}
+
+impl Stream for TcpStream {
+    fn try_clone(&self) -> io::Result<Box<dyn Stream>> {
+        Ok(Box::new(TcpStream::try_clone(self)?))
+    }
+
+    fn peer(&self) -> String {
+        match self.peer_addr() {
+            Ok(address) => address.to_string(),
+            Err(_) => String::from("disconnected tcp client"),
+        }
+    }
+
+    fn shutdown(&self) -> io::Result<()> {
+        TcpStream::shutdown(self, Shutdown::Both)
+    }
+
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        TcpStream::set_read_timeout(self, timeout)
+    }
+}
+
+#[cfg(unix)]
+impl Stream for UnixStream {
+    fn try_clone(&self) -> io::Result<Box<dyn Stream>> {
+        Ok(Box::new(UnixStream::try_clone(self)?))
+    }
+
+    fn peer(&self) -> String {
+        // Clients rarely bind their end to a path, so the socket they connected to is more useful
+        match self.local_addr().ok().and_then(|address| address.as_pathname().map(|path| path.display().to_string())) {
+            Some(path) => format!("unix:{}", path),
+            None => String::from("unix socket"),
+        }
+    }
+
+    fn shutdown(&self) -> io::Result<()> {
+        UnixStream::shutdown(self, Shutdown::Both)
+    }
+
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        UnixStream::set_read_timeout(self, timeout)
+    }
+}
+
+impl Stream for TlsStream {
+    fn try_clone(&self) -> io::Result<Box<dyn Stream>> {
+        Ok(Box::new(self.clone()))
+    }
+
+    fn peer(&self) -> String {
+        match self.peer_addr() {
+            Ok(address) => format!("tls:{}", address),
+            Err(_) => String::from("disconnected tls client"),
+        }
+    }
+
+    fn shutdown(&self) -> io::Result<()> {
+        TlsStream::shutdown(self)
+    }
+
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        TlsStream::set_read_timeout(self, timeout);
+        Ok(())
+    }
+}
+
+/// Connection to a client that whole messages are read from and written to
+pub trait Transport: Send {
+    /// Reads the next message, the codec is only used by transports that don't keep messages apart themselves
+    fn read_frame(&mut self, codec: &dyn Codec, max_size: u64) -> Result<Vec<u8>, read::Error>;
+
+    fn write_frame(&mut self, codec: &dyn Codec, frame: &[u8]) -> io::Result<()>;
+
+    /// Waits at most the timeout for the next message to start arriving without reading any of it
+    /// True means there's something to read, which may also be the end of the connection
+    fn wait_for_frame(&mut self, timeout: Duration) -> io::Result<bool>;
+
+    /// Other end of the connection, for logging
+    fn peer(&self) -> String;
+
+    fn shutdown(&mut self) -> io::Result<()>;
+
+    /// Reads fail with `read::ErrorKind::TimedOut` when nothing arrives in time
+    fn set_read_timeout(&mut self, timeout: Option<Duration>) -> io::Result<()>;
+}
+
+/// Messages framed by a codec on top of a byte stream
+pub struct StreamTransport {
+    stream: Box<dyn Stream>,
+    reader: BufReader<Box<dyn Stream>>,
+    /// Timeout that applies outside of `wait_for_frame`
+    read_timeout: Option<Duration>,
+}
+
+impl StreamTransport {
+    pub fn new(stream: impl Stream + 'static) -> io::Result<Self> {
+        let reader = BufReader::new(stream.try_clone()?);
+
+        Ok(Self {
+            stream: Box::new(stream),
+            reader,
+            read_timeout: None,
+        })
+    }
+}
+
+impl Transport for StreamTransport {
+    fn read_frame(&mut self, codec: &dyn Codec, max_size: u64) -> Result<Vec<u8>, read::Error> {
+        read::read_frame(&mut self.reader, codec, max_size)
+    }
+
+    fn write_frame(&mut self, codec: &dyn Codec, frame: &[u8]) -> io::Result<()> {
+        network::send_frame(&mut self.stream, codec, frame)
+    }
+
+    fn wait_for_frame(&mut self, timeout: Duration) -> io::Result<bool> {
+        if !self.reader.buffer().is_empty() {
+            return Ok(true);
+        }
+
+        // Filling the buffer doesn't consume anything, so timing out can't break a message in half
+        self.stream.set_read_timeout(Some(timeout))?;
+        let filled = self.reader.fill_buf().map(|_| ());
+        self.stream.set_read_timeout(self.read_timeout)?;
+
+        is_ready(filled)
+    }
+
+    fn peer(&self) -> String {
+        self.stream.peer()
+    }
+
+    fn shutdown(&mut self) -> io::Result<()> {
+        self.stream.shutdown()
+    }
+
+    fn set_read_timeout(&mut self, timeout: Option<Duration>) -> io::Result<()> {
+        self.read_timeout = timeout;
+        self.stream.set_read_timeout(timeout)
+    }
+}
+
+enum WebSocketState {
+    /// Stream that hasn't done the WebSocket handshake yet
+    Accepted(Box<dyn Stream>),
+    Open(Box<WebSocket<Box<dyn Stream>>>),
+    /// Handshake failed, the stream can't be used anymore
+    Failed,
+}
+
+/// Every WebSocket message is one protocol message, so browsers don't need to deal with length prefixes
+pub struct WebSocketTransport {
+    state: WebSocketState,
+    config: WebSocketConfig,
+    peer: String,
+    /// Message `wait_for_frame` read, the next `read_frame` returns it
+    pending: Option<tungstenite::Result<WebSocketMessage>>,
+    /// Timeout that applies outside of `wait_for_frame`
+    read_timeout: Option<Duration>,
+}
+
+impl WebSocketTransport {
+    /// Time the client has to finish the WebSocket handshake
+    const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+    /// Server end of the accepted stream, the WebSocket handshake is done by the first read or write
+    /// The stream can be a `TlsStream` for secure WebSockets
+    pub fn accept(stream: impl Stream + 'static, max_frame_size: u64) -> io::Result<Self> {
+        let peer = format!("ws:{}", stream.peer());
+        let config = WebSocketConfig {
+            max_message_size: Some(max_frame_size as usize),
+            max_frame_size: Some(max_frame_size as usize),
+            ..WebSocketConfig::default()
+        };
+
+        Ok(Self { state: WebSocketState::Accepted(Box::new(stream)), config, peer, pending: None, read_timeout: None })
+    }
+
+    /// Socket after the handshake, which is done first if it hasn't been already
+    fn socket(&mut self) -> io::Result<&mut WebSocket<Box<dyn Stream>>> {
+        if let WebSocketState::Accepted(_) = self.state {
+            let stream = match mem::replace(&mut self.state, WebSocketState::Failed) {
+                WebSocketState::Accepted(stream) => stream,
+                _ => unreachable!(),
+            };
+
+            stream.set_read_timeout(Some(Self::HANDSHAKE_TIMEOUT))?;
+            let socket = tungstenite::accept_with_config(stream, Some(self.config)).map_err(|e| io::Error::other(e.to_string()))?;
+            socket.get_ref().set_read_timeout(self.read_timeout)?;
+            self.state = WebSocketState::Open(Box::new(socket));
+        }
+
+        match &mut self.state {
+            WebSocketState::Open(socket) => Ok(socket),
+            _ => Err(io::Error::new(io::ErrorKind::NotConnected, "WebSocket handshake failed")),
+        }
+    }
+}
+
+impl Transport for WebSocketTransport {
+    fn read_frame(&mut self, _codec: &dyn Codec, max_size: u64) -> Result<Vec<u8>, read::Error> {
+        loop {
+            let message = match self.pending.take() {
+                Some(message) => message,
+                None => self.socket()?.read(),
+            };
+
+            let frame = match message {
+                Ok(WebSocketMessage::Text(text)) => text.into_bytes(),
+                Ok(WebSocketMessage::Binary(bytes)) => bytes,
+                // Pings get answered by the socket itself on the next read or write
+                Ok(WebSocketMessage::Ping(_)) | Ok(WebSocketMessage::Pong(_)) | Ok(WebSocketMessage::Frame(_)) => continue,
+                Ok(WebSocketMessage::Close(_))
+                | Err(tungstenite::Error::ConnectionClosed)
+                | Err(tungstenite::Error::AlreadyClosed) => return Err(read::Error::from(read::ErrorKind::ConnectionLost)),
+                Err(tungstenite::Error::Capacity(_)) => return Err(read::Error::from(read::ErrorKind::FrameTooLarge)),
+                // Socket keeps the partly read message, so a timeout doesn't break it like it breaks a byte stream
+                Err(tungstenite::Error::Io(e)) => return Err(read::Error::from(e)),
+                Err(e) => return Err(read::Error::from(io::Error::other(e))),
+            };
+
+            if frame.len() as u64 > max_size {
+                return Err(read::Error::from(read::ErrorKind::FrameTooLarge));
+            }
+
+            return Ok(frame);
+        }
+    }
+
+    fn write_frame(&mut self, _codec: &dyn Codec, frame: &[u8]) -> io::Result<()> {
+        // Text and JSON go out as text messages that browsers can use as they are
+        let message = match String::from_utf8(frame.to_vec()) {
+            Ok(text) => WebSocketMessage::Text(text),
+            Err(e) => WebSocketMessage::Binary(e.into_bytes()),
+        };
+
+        self.socket()?.send(message).map_err(|e| match e {
+            tungstenite::Error::Io(e) => e,
+            e => io::Error::other(e),
+        })
+    }
+
+    fn wait_for_frame(&mut self, timeout: Duration) -> io::Result<bool> {
+        if self.pending.is_some() {
+            return Ok(true);
+        }
+
+        let read_timeout = self.read_timeout;
+        let socket = self.socket()?;
+        socket.get_ref().set_read_timeout(Some(timeout))?;
+        let message = socket.read();
+        socket.get_ref().set_read_timeout(read_timeout)?;
+
+        match message {
+            // Pings are answered by the socket, there's still nothing for the caller
+            Ok(WebSocketMessage::Ping(_)) | Ok(WebSocketMessage::Pong(_)) | Ok(WebSocketMessage::Frame(_)) => Ok(false),
+            Err(tungstenite::Error::Io(e)) if is_timeout(&e) => Ok(false),
+            message => {
+                self.pending = Some(message);
+                Ok(true)
+            },
+        }
+    }
+
+    fn peer(&self) -> String {
+        self.peer.clone()
+    }
+
+    fn shutdown(&mut self) -> io::Result<()> {
+        match &mut self.state {
+            WebSocketState::Accepted(stream) => stream.shutdown(),
+            WebSocketState::Open(socket) => {
+                // Client may be gone already, the stream gets shut down either way
+                if let Err(e) = socket.close(None) {
+                    debug!("Closing WebSocket of {} failed: {}", self.peer, e);
+                }
+
+                socket.get_ref().shutdown()
+            },
+            WebSocketState::Failed => Ok(()),
+        }
+    }
+
+    /// Handshake has its own timeout, this one applies once it's done
+    fn set_read_timeout(&mut self, timeout: Option<Duration>) -> io::Result<()> {
+        self.read_timeout = timeout;
+        match &self.state {
+            WebSocketState::Open(socket) => socket.get_ref().set_read_timeout(timeout),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Read timeouts show up as either of these depending on the platform
+fn is_timeout(error: &io::Error) -> bool {
+    error.kind() == io::ErrorKind::WouldBlock || error.kind() == io::ErrorKind::TimedOut
+}
+
+/// Result of waiting for a message, errors other than timing out are left for the read to report
+fn is_ready(result: io::Result<()>) -> io::Result<bool> {
+    match result {
+        Err(e) if is_timeout(&e) => Ok(false),
+        _ => Ok(true),
+    }
+}
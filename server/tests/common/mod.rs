@@ -0,0 +1,105 @@
+//! Helpers shared by the integration tests, each test file uses only some of them
+#![allow(dead_code)]
+
+use network::envelope::Kind;
+use network::{Message, Protocol, Request, Response, ServerMessage};
+use server::Server;
+use std::io::BufReader;
+use std::net::{TcpListener, TcpStream};
+use std::thread;
+use std::time::{Duration, Instant};
+use tungstenite::{Message as WebSocketMessage, WebSocket};
+
+pub type Socket = WebSocket<tungstenite::stream::MaybeTlsStream<TcpStream>>;
+
+/// Address nothing is listening on right now
+pub fn free_address() -> String {
+    TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().to_string()
+}
+
+/// Runs the server on its own thread and waits until the address accepts connections
+pub fn start(mut server: Server<String>, address: &str) {
+    thread::spawn(move || server.start().unwrap());
+
+    // Shuffle self-test runs before the listeners are opened
+    let started = Instant::now();
+    while TcpStream::connect(address).is_err() {
+        assert!(started.elapsed() < Duration::from_secs(30), "Server didn't start");
+        thread::sleep(Duration::from_millis(50));
+    }
+}
+
+/// Starts a server with a WebSocket listener, returns the TCP and WebSocket addresses
+pub fn start_websocket_server() -> (String, String) {
+    let (tcp, websocket) = (free_address(), free_address());
+
+    let mut server = Server::new(tcp.clone());
+    server.set_websocket_address(websocket.clone());
+    start(server, &websocket);
+
+    (tcp, websocket)
+}
+
+/// WebSocket client that has done the handshake
+pub fn connect(address: &str, hello: &str) -> Socket {
+    let (mut socket, _) = tungstenite::connect(format!("ws://{}", address)).unwrap();
+    socket.send(WebSocketMessage::Text(hello.to_string())).unwrap();
+    assert!(receive(&mut socket).starts_with("hello 1"));
+    socket
+}
+
+pub fn receive(socket: &mut Socket) -> String {
+    match socket.read().unwrap() {
+        WebSocketMessage::Text(text) => text,
+        message => panic!("Expected a text message, got {:?}", message),
+    }
+}
+
+/// Reply to the message, events sent before it are skipped
+pub fn request(socket: &mut Socket, id: u32, message: Message) -> Response {
+    socket.send(WebSocketMessage::Text(Request::new(Some(id), message).to_json())).unwrap();
+
+    loop {
+        let response = Response::from_json(&receive(socket)).unwrap();
+        if response.kind == Kind::Reply {
+            return response;
+        }
+    }
+}
+
+/// JSON client over plain TCP
+pub struct Client {
+    stream: TcpStream,
+    reader: BufReader<TcpStream>,
+}
+
+impl Client {
+    pub fn connect(address: &str) -> Self {
+        let stream = TcpStream::connect(address).unwrap();
+        let mut reader = BufReader::new(stream.try_clone().unwrap());
+        network::send(&stream, "hello 1 json").unwrap();
+        assert_eq!("hello 1 json binary", network::read::read(&mut reader).unwrap());
+
+        let mut client = Self { stream, reader };
+        assert_eq!(ServerMessage::Welcome, client.receive().message);
+        client
+    }
+
+    pub fn receive(&mut self) -> Response {
+        let frame = network::read::read_frame(&mut self.reader, Protocol::Json.codec(), 1024).unwrap();
+        Response::decode(&frame, Protocol::Json).unwrap()
+    }
+
+    /// Reply to the message, events sent before it are skipped
+    pub fn request(&mut self, id: u32, message: Message) -> Response {
+        let frame = Request::new(Some(id), message).encode(Protocol::Json);
+        network::send_frame(&self.stream, Protocol::Json.codec(), &frame).unwrap();
+
+        loop {
+            let response = self.receive();
+            if response.kind == Kind::Reply {
+                return response;
+            }
+        }
+    }
+}
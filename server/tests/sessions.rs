@@ -0,0 +1,103 @@
+mod common;
+
+use common::{connect, receive, request, start_websocket_server};
+use network::error::Error;
+use network::{Message, Response, ServerMessage};
+use server::auth;
+
+#[test]
+fn passwords() {
+    let (_, websocket) = start_websocket_server();
+    let mut socket = connect(&websocket, "hello 1 json");
+    receive(&mut socket);
+
+    let login = |password: &str| Message::Login { name: "frank".to_string(), password: password.to_string() };
+    let code = |response: Response| match response.message {
+        ServerMessage::Error { code, .. } => code,
+        message => panic!("Expected an error, got {:?}", message),
+    };
+
+    let short = Message::Register { name: "frank".to_string(), password: "short".to_string() };
+    assert_eq!(Error::WeakPassword.code(), code(request(&mut socket, 1, short)));
+    let register = Message::Register { name: "Frank".to_string(), password: "password".to_string() };
+    assert_eq!(ServerMessage::LoggedIn { balance: 0 }, request(&mut socket, 2, register).message);
+    let again = Message::Register { name: "frank".to_string(), password: "other password".to_string() };
+    assert_eq!(Error::AccountExists.code(), code(request(&mut socket, 3, again)));
+
+    let passwd = Message::Passwd { current: "password".to_string(), new: "new password".to_string() };
+    assert_eq!(ServerMessage::PasswordChanged, request(&mut socket, 4, passwd).message);
+    assert_eq!(Error::InvalidCredentials.code(), code(request(&mut socket, 5, login("password"))));
+    assert!(matches!(request(&mut socket, 6, login("new password")).message, ServerMessage::LoggedIn { .. }));
+
+    // Right password doesn't help once the account is locked
+    for id in 7..7 + auth::MAX_FAILURES {
+        assert_eq!(Error::InvalidCredentials.code(), code(request(&mut socket, id, login("guess"))));
+    }
+    assert_eq!(Error::AccountLocked.code(), code(request(&mut socket, 20, login("new password"))));
+}
+
+#[test]
+fn resume_after_disconnect() {
+    let (_, websocket) = start_websocket_server();
+    let mut socket = connect(&websocket, "hello 1 json");
+    receive(&mut socket);
+
+    request(&mut socket, 1, Message::Register { name: "gina".to_string(), password: "password".to_string() });
+    let token = match Response::from_json(&receive(&mut socket)).unwrap().message {
+        ServerMessage::Session { token, .. } => token,
+        message => panic!("Expected a session, got {:?}", message),
+    };
+    request(&mut socket, 2, Message::Deposit(30));
+    request(&mut socket, 3, Message::Start(10));
+    drop(socket);
+
+    // New connection gets the same game with the round still going
+    let mut socket = connect(&websocket, "hello 1 json");
+    receive(&mut socket);
+    assert_eq!(ServerMessage::LoggedIn { balance: 30 }, request(&mut socket, 1, Message::Resume(token.clone())).message);
+    let token = match Response::from_json(&receive(&mut socket)).unwrap().message {
+        ServerMessage::Session { token: new, .. } => {
+            assert_ne!(token, new);
+            token
+        },
+        message => panic!("Expected a session, got {:?}", message),
+    };
+    assert!(matches!(Response::from_json(&receive(&mut socket)).unwrap().message, ServerMessage::HandState { .. }));
+
+    // Tokens can only be used once
+    match request(&mut socket, 2, Message::Resume(token)).message {
+        ServerMessage::Error { code, .. } => assert_eq!(Error::InvalidSession.code(), code),
+        message => panic!("Expected an error, got {:?}", message),
+    }
+}
+
+#[test]
+fn passwd_logs_out_other_connections() {
+    let (_, websocket) = start_websocket_server();
+    let mut first = connect(&websocket, "hello 1 json");
+    receive(&mut first);
+    let mut second = connect(&websocket, "hello 1 json");
+    receive(&mut second);
+
+    request(&mut first, 1, Message::Register { name: "henry".to_string(), password: "password".to_string() });
+    request(&mut second, 1, Message::Login { name: "henry".to_string(), password: "password".to_string() });
+    let token = match Response::from_json(&receive(&mut second)).unwrap().message {
+        ServerMessage::Session { token, .. } => token,
+        message => panic!("Expected a session, got {:?}", message),
+    };
+
+    let passwd = Message::Passwd { current: "password".to_string(), new: "new password".to_string() };
+    assert_eq!(ServerMessage::PasswordChanged, request(&mut first, 2, passwd).message);
+    assert_eq!(Response::event(ServerMessage::error(Error::NotLoggedIn, Default::default())), Response::from_json(&receive(&mut second)).unwrap());
+    match request(&mut second, 2, Message::Deposit(10)).message {
+        ServerMessage::Error { code, .. } => assert_eq!(Error::NotLoggedIn.code(), code),
+        message => panic!("Expected an error, got {:?}", message),
+    }
+    match request(&mut second, 3, Message::Resume(token)).message {
+        ServerMessage::Error { code, .. } => assert_eq!(Error::InvalidSession.code(), code),
+        message => panic!("Expected an error, got {:?}", message),
+    }
+
+    // Connection that changed the password stays logged in
+    assert_eq!(ServerMessage::Deposited { amount: 10, balance: 10 }, request(&mut first, 3, Message::Deposit(10)).message);
+}
@@ -0,0 +1,72 @@
+mod common;
+
+use common::{free_address, Client};
+use network::{Message, ServerMessage};
+use server::storage::{FileStorage, Storage};
+use server::Server;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+/// Starts a server keeping its accounts in the file, returns its address
+fn start_server(accounts: &Path) -> String {
+    let address = free_address();
+
+    let mut server = Server::new(address.clone());
+    server.set_storage(FileStorage::open(accounts).unwrap());
+    common::start(server, &address);
+
+    address
+}
+
+#[test]
+fn accounts_survive_restart() {
+    let accounts = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("accounts_survive_restart.json");
+    let _ = std::fs::remove_file(&accounts);
+
+    let mut client = Client::connect(&start_server(&accounts));
+    client.request(1, Message::Register { name: "Erin".to_string(), password: "password".to_string() });
+    client.request(2, Message::Deposit(50));
+    assert!(matches!(client.request(3, Message::Start(10)).message, ServerMessage::HandState { .. }));
+
+    // Account is saved before the reply is sent
+    assert!(FileStorage::open(&accounts).unwrap().load("erin").unwrap().round.is_some());
+
+    // Another server with the same file has the balance and the unfinished round
+    let mut client = Client::connect(&start_server(&accounts));
+    let response = client.request(1, Message::Login { name: "erin".to_string(), password: "password".to_string() });
+    assert_eq!(ServerMessage::LoggedIn { balance: 50 }, response.message);
+    assert!(!response.actions.is_empty());
+    assert!(matches!(client.receive().message, ServerMessage::Session { .. }));
+    assert!(matches!(client.receive().message, ServerMessage::HandState { .. }));
+    assert!(matches!(client.request(2, Message::Stand).message, ServerMessage::RoundResult { .. }));
+}
+
+#[test]
+fn accounts_without_password() {
+    let accounts = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("accounts_without_password.json");
+    std::fs::write(&accounts, r#"{"grace":{"balance":70,"last_bet":null,"round":null}}"#).unwrap();
+
+    // Registering can't take the account over
+    let mut client = Client::connect(&start_server(&accounts));
+    let register = Message::Register { name: "grace".to_string(), password: "password".to_string() };
+    match client.request(1, register).message {
+        ServerMessage::Error { code, .. } => assert_eq!(network::error::Error::AccountExists.code(), code),
+        message => panic!("Expected an error, got {:?}", message),
+    }
+    assert_eq!(None, FileStorage::open(&accounts).unwrap().credential("grace"));
+
+    // Operator gives it a password, then it can be logged in to
+    let mut set_password = Command::new(env!("CARGO_BIN_EXE_server"))
+        .args(["--accounts", accounts.to_str().unwrap(), "--set-password", "Grace"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .spawn()
+        .unwrap();
+    set_password.stdin.take().unwrap().write_all(b"new password\n").unwrap();
+    assert!(set_password.wait().unwrap().success());
+
+    let mut client = Client::connect(&start_server(&accounts));
+    let login = Message::Login { name: "grace".to_string(), password: "new password".to_string() };
+    assert_eq!(ServerMessage::LoggedIn { balance: 70 }, client.request(1, login).message);
+}
@@ -0,0 +1,93 @@
+mod common;
+
+use common::free_address;
+use network::tls::{self, Fingerprint, TlsStream};
+use network::{Message, Protocol, Request, Response, ServerMessage};
+use server::Server;
+use std::io::BufReader;
+use std::net::TcpStream;
+use std::path::PathBuf;
+use std::time::Duration;
+use tungstenite::Message as WebSocketMessage;
+
+/// Self-signed certificate for localhost and its key
+fn certificate() -> (PathBuf, PathBuf) {
+    let certs = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests").join("certs");
+    (certs.join("cert.pem"), certs.join("key.pem"))
+}
+
+/// Starts a server that only accepts TLS connections, returns its TCP and WebSocket addresses
+fn start_server() -> (String, String) {
+    let (address, websocket) = (free_address(), free_address());
+    let (certificate, key) = certificate();
+
+    let mut server = Server::new(address.clone());
+    server.set_websocket_address(websocket.clone());
+    server.set_tls(certificate, key);
+    common::start(server, &address);
+
+    (address, websocket)
+}
+
+fn connect(address: &str, pin: Fingerprint) -> std::io::Result<TlsStream> {
+    let config = tls::client_config(None, Some(pin)).unwrap();
+    TlsStream::connect(TcpStream::connect(address).unwrap(), config, "localhost")
+}
+
+fn receive(reader: &mut BufReader<TlsStream>) -> Response {
+    let frame = network::read::read_frame(reader, Protocol::Json.codec(), 1024).unwrap();
+    Response::decode(&frame, Protocol::Json).unwrap()
+}
+
+#[test]
+fn pinned_certificate() {
+    let (address, _) = start_server();
+    let pin = tls::certificate_fingerprint(&certificate().0).unwrap();
+
+    let stream = connect(&address, pin).unwrap();
+    let mut reader = BufReader::new(stream.clone());
+    network::send(stream.clone(), "hello 1 json").unwrap();
+    assert_eq!("hello 1 json binary", network::read::read(&mut reader).unwrap());
+
+    assert_eq!(Response::event(ServerMessage::Welcome), receive(&mut reader));
+
+    for request in [Request::new(Some(1), Message::Register { name: "dave".to_string(), password: "password".to_string() }), Request::new(Some(2), Message::Deposit(15))].iter() {
+        let frame = request.encode(Protocol::Json);
+        network::send_frame(stream.clone(), Protocol::Json.codec(), &frame).unwrap();
+    }
+
+    assert_eq!(Response::reply(Some(1), ServerMessage::LoggedIn { balance: 0 }), receive(&mut reader));
+    assert!(matches!(receive(&mut reader).message, ServerMessage::Session { .. }));
+    assert_eq!(Response::reply(Some(2), ServerMessage::Deposited { amount: 15, balance: 15 }), receive(&mut reader));
+}
+
+#[test]
+fn wrong_pin_is_refused() {
+    let (address, _) = start_server();
+
+    assert!(connect(&address, Fingerprint::of(b"some other certificate")).is_err());
+}
+
+#[test]
+fn cleartext_clients_get_nothing() {
+    let (address, _) = start_server();
+
+    let stream = TcpStream::connect(&address).unwrap();
+    stream.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+    network::send(&stream, "hello 1").unwrap();
+    assert!(network::read::read(&mut BufReader::new(&stream)).is_err());
+}
+
+#[test]
+fn secure_websocket() {
+    let (_, websocket) = start_server();
+    let pin = tls::certificate_fingerprint(&certificate().0).unwrap();
+
+    let stream = connect(&websocket, pin).unwrap();
+    let (mut socket, _) = tungstenite::client(format!("wss://{}", websocket), stream).unwrap();
+    socket.send(WebSocketMessage::Text("hello 1".to_string())).unwrap();
+    assert_eq!(WebSocketMessage::Text("hello 1 json binary".to_string()), socket.read().unwrap());
+
+    // WebSockets are encrypted just like plain TCP connections
+    assert!(tungstenite::connect(format!("ws://{}", websocket)).is_err());
+}
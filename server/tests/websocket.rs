@@ -0,0 +1,146 @@
+mod common;
+
+use common::{connect, receive, request, start_websocket_server, Socket};
+use network::command::Command;
+use network::server_message::Action;
+use network::{Message, Response, ServerMessage};
+use std::io::BufReader;
+use std::net::TcpStream;
+use std::time::{Duration, Instant};
+use tungstenite::Message as WebSocketMessage;
+
+#[test]
+fn json_over_websocket() {
+    let (_, websocket) = start_websocket_server();
+    let mut socket = connect(&websocket, "hello 1 json");
+
+    assert_eq!(Response::event(ServerMessage::Welcome), Response::from_json(&receive(&mut socket)).unwrap());
+    assert_eq!(
+        Response::reply(Some(1), ServerMessage::LoggedIn { balance: 0 }),
+        request(&mut socket, 1, Message::Register { name: "alice".to_string(), password: "password".to_string() })
+    );
+    assert_eq!(
+        Response::reply(Some(2), ServerMessage::Deposited { amount: 25, balance: 25 }),
+        request(&mut socket, 2, Message::Deposit(25))
+    );
+
+    let response = request(&mut socket, 3, Message::Start(10));
+    match response.message {
+        ServerMessage::HandState { dealer, player } => {
+            // Hole card stays hidden until the round is over
+            assert_eq!(1, dealer.cards.len());
+            assert_eq!(2, player.cards.len());
+            let actions: Vec<Action> = response.actions.iter().map(|legal| legal.action).collect();
+            assert_eq!(vec![Action::Hit, Action::Stand, Action::Double, Action::Surrender], actions);
+            assert_eq!(5, response.actions[3].cost);
+        },
+        message => panic!("Expected the round to start, got {:?}", message),
+    }
+}
+
+#[test]
+fn silent_client_doesnt_hold_up_others() {
+    let (_, websocket) = start_websocket_server();
+
+    // Never starts the WebSocket handshake
+    let _silent = TcpStream::connect(&websocket).unwrap();
+    let started = Instant::now();
+    let mut socket = connect(&websocket, "hello 1 json");
+    assert!(receive(&mut socket).contains("welcome"));
+    assert!(started.elapsed() < Duration::from_secs(5));
+}
+
+#[test]
+fn text_over_websocket() {
+    let (_, websocket) = start_websocket_server();
+    let mut socket = connect(&websocket, "hello 1");
+
+    assert!(receive(&mut socket).starts_with("Type \"exit\""));
+    socket.send(WebSocketMessage::Text("#4 register carol password".to_string())).unwrap();
+    assert!(receive(&mut socket).starts_with("#4 Successfully logged in"));
+    assert!(receive(&mut socket).starts_with("Type \"resume "));
+    socket.send(WebSocketMessage::Text("fly".to_string())).unwrap();
+    assert_eq!("Server couldn't understand the command", receive(&mut socket));
+
+    socket.send(WebSocketMessage::Text("lang et".to_string())).unwrap();
+    assert_eq!("Keel on nüüd eesti", receive(&mut socket));
+    socket.send(WebSocketMessage::Text("#5 deposit 10".to_string())).unwrap();
+    assert_eq!("#5 Kontole lisati 10", receive(&mut socket));
+
+    // Text clients get the default aliases
+    socket.send(WebSocketMessage::Text("#6 bet 5".to_string())).unwrap();
+    assert!(receive(&mut socket).starts_with("#6 Diileri käsi"));
+    socket.send(WebSocketMessage::Text("rebet".to_string())).unwrap();
+    assert_eq!("Mänguvoor juba käib", receive(&mut socket));
+
+    // Id is kept when only the command is wrong
+    socket.send(WebSocketMessage::Text("#7 bet".to_string())).unwrap();
+    assert!(receive(&mut socket).starts_with("#7 "));
+}
+
+/// Commands `help` lists as available right now
+fn available(socket: &mut Socket, id: u32) -> Vec<Command> {
+    match request(socket, id, Message::Help(None)).message {
+        ServerMessage::Help { commands } => commands.iter().map(|help| help.command).collect(),
+        message => panic!("Expected help, got {:?}", message),
+    }
+}
+
+#[test]
+fn help_follows_the_game() {
+    let (_, websocket) = start_websocket_server();
+    let mut socket = connect(&websocket, "hello 1 json");
+    receive(&mut socket);
+
+    let commands = available(&mut socket, 1);
+    assert!(commands.contains(&Command::Login) && !commands.contains(&Command::Start));
+    match request(&mut socket, 2, Message::Help(Some(Command::Hit))).message {
+        ServerMessage::Help { commands } => {
+            assert_eq!(1, commands.len());
+            assert_eq!("hit", commands[0].usage);
+            assert!(!commands[0].available);
+        },
+        message => panic!("Expected help, got {:?}", message),
+    }
+
+    request(&mut socket, 3, Message::Register { name: "erin".to_string(), password: "password".to_string() });
+    request(&mut socket, 4, Message::Deposit(50));
+    let commands = available(&mut socket, 5);
+    assert!(commands.contains(&Command::Start) && !commands.contains(&Command::Hit));
+
+    request(&mut socket, 6, Message::Start(10));
+    let commands = available(&mut socket, 7);
+    assert!(commands.contains(&Command::Hit) && commands.contains(&Command::Stand) && !commands.contains(&Command::Start));
+}
+
+#[test]
+fn sessions_are_shared_with_tcp() {
+    let (tcp, websocket) = start_websocket_server();
+
+    let mut socket = connect(&websocket, "hello 1 json");
+    receive(&mut socket);
+    request(&mut socket, 1, Message::Register { name: "bob".to_string(), password: "password".to_string() });
+    request(&mut socket, 2, Message::Deposit(40));
+
+    let stream = TcpStream::connect(&tcp).unwrap();
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+    for message in ["hello 1", "login BOB password", "balance"].iter() {
+        network::send(&stream, message).unwrap();
+    }
+
+    assert_eq!("hello 1 json binary", network::read::read(&mut reader).unwrap());
+    network::read::read(&mut reader).unwrap();
+    assert!(network::read::read(&mut reader).unwrap().contains("Current balance: 40"));
+    assert!(network::read::read(&mut reader).unwrap().starts_with("Type \"resume "));
+    assert_eq!("40", network::read::read(&mut reader).unwrap());
+
+    // Changes made on one connection show up on the other without asking
+    request(&mut socket, 3, Message::Deposit(10));
+    assert_eq!("10 deposited to the account", network::read::read(&mut reader).unwrap());
+    network::send(&stream, "deposit 5").unwrap();
+    assert_eq!("5 deposited to the account", network::read::read(&mut reader).unwrap());
+    assert_eq!(
+        Response::event(ServerMessage::Deposited { amount: 5, balance: 55 }),
+        Response::from_json(&receive(&mut socket)).unwrap()
+    );
+}